@@ -1,5 +1,6 @@
 use crate::math::*;
 
+#[derive(Clone, Copy)]
 pub struct Aabb {
     min: Vec3,
     max: Vec3,
@@ -13,14 +14,47 @@ impl Aabb {
         };
     }
 
-    fn new_default() -> Aabb {
+    pub fn new_default() -> Aabb {
         return Aabb {
             min: Vec3::new_default(),
             max: Vec3::new_default(),
         };
     }
 
-    fn intersect(&self, r: &Ray, mut tmin: f64, mut tmax: f64) -> bool {
+    pub fn min(&self) -> Vec3 {
+        return self.min;
+    }
+
+    pub fn max(&self) -> Vec3 {
+        return self.max;
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        return 0.5 * (self.min + self.max);
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        return self.max() - self.min();
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.extent();
+        return 2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x());
+    }
+
+    pub fn longest_axis(&self) -> i32 {
+        let d = self.extent();
+
+        if d.x() > d.y() && d.x() > d.z() {
+            return 0;
+        } else if d.y() > d.z() {
+            return 1;
+        }
+
+        return 2;
+    }
+
+    pub fn intersect(&self, r: &Ray, mut tmin: f64, mut tmax: f64) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / r.direction()[a];
             let mut t0 = (self.min[a] - r.origin()[a]) * inv_d;
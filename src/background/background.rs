@@ -0,0 +1,44 @@
+use super::*;
+
+use crate::math::*;
+
+// The radiance of rays that leave the scene without hitting anything.
+pub enum Background {
+    Solid(Color),
+    // Blends from the bottom color straight down to the top color straight
+    // up.
+    Gradient(Color, Color),
+    Environment(EnvironmentMap),
+    Sky(Box<SkyBackground>),
+}
+
+impl Background {
+    // The sky of the original renderer, white near the horizon and blue
+    // above.
+    pub fn new_default() -> Background {
+        return Background::Gradient(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0));
+    }
+
+    // The map used to importance sample the background, if it's worth
+    // sampling.
+    pub fn importance_map(&self) -> Option<&EnvironmentMap> {
+        return match self {
+            Background::Environment(environment) => Some(environment),
+            Background::Sky(sky) => Some(sky.importance_map()),
+            _ => None,
+        };
+    }
+
+    pub fn color(&self, direction: Vec3) -> Color {
+        return match self {
+            Background::Solid(color) => *color,
+            Background::Gradient(bottom, top) => {
+                let unit_direction = unit_vector(direction);
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            },
+            Background::Environment(environment) => environment.radiance(direction),
+            Background::Sky(sky) => sky.sky().radiance(direction),
+        };
+    }
+}
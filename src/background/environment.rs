@@ -0,0 +1,183 @@
+use crate::image::Image;
+use crate::math::*;
+use crate::random::Sampler;
+
+use std::f64::consts::PI;
+
+// An equirectangular (latitude-longitude) environment map. The top row of
+// the image is straight up, and without rotation the center column looks
+// down the -z axis.
+pub struct EnvironmentMap {
+    image: Image,
+    intensity: f64,
+    sin_rotation: f64,
+    cos_rotation: f64,
+    // Cumulative distribution of the rows, and of the pixels within each
+    // row, proportional to luminance times the solid angle of the pixel.
+    // Both have one more entry than there are rows or pixels in a row.
+    marginal_cdf: Vec<f64>,
+    conditional_cdf: Vec<f64>,
+}
+
+fn luminance(color: Color) -> f64 {
+    return (0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()).max(0.0);
+}
+
+// Turns the weights into a normalized cumulative distribution, which is
+// uniform if all weights are zero. Returns the sum of the weights.
+fn build_cdf(weights: &[f64], cdf: &mut Vec<f64>) -> f64 {
+    let start = cdf.len();
+    let mut sum = 0.0;
+    cdf.push(0.0);
+
+    for &weight in weights {
+        sum += weight;
+        cdf.push(sum);
+    }
+
+    for (i, value) in cdf[start..].iter_mut().enumerate() {
+        *value = if sum > 0.0 { *value / sum } else { i as f64 / weights.len() as f64 };
+    }
+
+    return sum;
+}
+
+// Finds the interval of the cumulative distribution that contains u, and
+// where u lies within it.
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let index = cdf.partition_point(|&value| value <= u).clamp(1, cdf.len() - 1) - 1;
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0.0 { (u - cdf[index]) / width } else { 0.5 };
+
+    return (index, clamp(offset, 0.0, 0.999999));
+}
+
+impl EnvironmentMap {
+    // The rotation is about the y axis, in degrees. The intensity scales the
+    // radiance of the image.
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> EnvironmentMap {
+        let width = image.width();
+        let height = image.height();
+
+        let mut conditional_cdf = Vec::with_capacity(height * (width + 1));
+        let mut row_weights = Vec::with_capacity(height);
+        let mut weights = vec![0.0; width];
+
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+
+            for (weight, &pixel) in weights.iter_mut().zip(image.row(y).iter()) {
+                *weight = luminance(pixel) * sin_theta;
+            }
+
+            row_weights.push(build_cdf(&weights, &mut conditional_cdf));
+        }
+
+        let mut marginal_cdf = Vec::with_capacity(height + 1);
+        build_cdf(&row_weights, &mut marginal_cdf);
+
+        let (sin_rotation, cos_rotation) = degrees_to_radians(rotation).sin_cos();
+
+        return EnvironmentMap {
+            image,
+            intensity,
+            sin_rotation,
+            cos_rotation,
+            marginal_cdf,
+            conditional_cdf,
+        };
+    }
+
+    // Turns a direction in the world into one in the frame of the image.
+    fn to_local(&self, d: Vec3) -> Vec3 {
+        return Vec3::new(self.cos_rotation * d.x() - self.sin_rotation * d.z(), d.y(), self.sin_rotation * d.x() + self.cos_rotation * d.z());
+    }
+
+    fn to_world(&self, d: Vec3) -> Vec3 {
+        return Vec3::new(self.cos_rotation * d.x() + self.sin_rotation * d.z(), d.y(), -self.sin_rotation * d.x() + self.cos_rotation * d.z());
+    }
+
+    // Image coordinates in [0, 1] of a direction in the frame of the image.
+    fn direction_to_uv(d: Vec3) -> (f64, f64) {
+        let d = unit_vector(d);
+        let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI);
+        let v = clamp(d.y(), -1.0, 1.0).acos() / PI;
+
+        return (u, v);
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = EnvironmentMap::direction_to_uv(self.to_local(direction));
+
+        return self.intensity * self.lookup(u, v);
+    }
+
+    // Picks a direction with a probability proportional to the brightness
+    // of the environment in that direction.
+    pub fn sample_direction(&self, sampler: &mut Sampler) -> Vec3 {
+        let width = self.image.width();
+
+        let (y, y_offset) = sample_cdf(&self.marginal_cdf, sampler.double_unit());
+        let row_cdf = &self.conditional_cdf[y * (width + 1)..(y + 1) * (width + 1)];
+        let (x, x_offset) = sample_cdf(row_cdf, sampler.double_unit());
+
+        let phi = ((x as f64 + x_offset) / width as f64 - 0.5) * 2.0 * PI;
+        let theta = (y as f64 + y_offset) / self.image.height() as f64 * PI;
+
+        let local = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+
+        return self.to_world(local);
+    }
+
+    // The probability density, with respect to solid angle, of
+    // sample_direction returning the given direction.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let width = self.image.width();
+        let height = self.image.height();
+
+        let (u, v) = EnvironmentMap::direction_to_uv(self.to_local(direction));
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((v * height as f64) as usize).min(height - 1);
+
+        let sin_theta = (PI * v).sin();
+
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let row_probability = self.marginal_cdf[y + 1] - self.marginal_cdf[y];
+        let row_cdf = &self.conditional_cdf[y * (width + 1)..(y + 1) * (width + 1)];
+        let pixel_probability = row_cdf[x + 1] - row_cdf[x];
+
+        // The density over the image, divided by the Jacobian of the mapping
+        // from image coordinates to directions.
+        let density = row_probability * pixel_probability * (width * height) as f64;
+
+        return density / (2.0 * PI * PI * sin_theta);
+    }
+
+    // Bilinear lookup at image coordinates in [0, 1], wrapping around
+    // horizontally.
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let width = self.image.width();
+        let height = self.image.height();
+
+        let x = u * width as f64 - 0.5;
+        let y = clamp(v * height as f64 - 0.5, 0.0, (height - 1) as f64);
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let column0 = (x0 as i64).rem_euclid(width as i64) as usize;
+        let column1 = (column0 + 1) % width;
+        let row0 = y0 as usize;
+        let row1 = (row0 + 1).min(height - 1);
+
+        let top = (1.0 - fx) * self.image.row(row0)[column0] + fx * self.image.row(row0)[column1];
+        let bottom = (1.0 - fx) * self.image.row(row1)[column0] + fx * self.image.row(row1)[column1];
+
+        return (1.0 - fy) * top + fy * bottom;
+    }
+}
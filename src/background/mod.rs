@@ -0,0 +1,8 @@
+#[allow(clippy::module_inception)]
+mod background;
+mod environment;
+mod sky;
+
+pub use background::*;
+pub use environment::*;
+pub use sky::*;
@@ -0,0 +1,254 @@
+use super::*;
+
+use crate::image::Image;
+use crate::math::*;
+
+use std::f64::consts::PI;
+
+// Angular radius of the sun as seen from the earth.
+const SUN_ANGULAR_RADIUS: f64 = 0.004654;
+
+// Converts luminance in kcd/m^2 to the units of the renderer, chosen so that
+// a white surface lit by the sun and sky around noon has a radiance of about
+// one.
+const SKY_SCALE: f64 = 0.02;
+
+// Radiance of the sun before it passes through the atmosphere, in the same
+// units. With the sun high up, it lights a horizontal surface about five
+// times as strongly as the sky, as on a clear day.
+const SUN_RADIANCE: f64 = 80000.0;
+
+// Samples per side of each pixel when finding how much of it the sun
+// covers.
+const SUN_SUBSAMPLES: usize = 8;
+
+// The direction through a point of an equirectangular image of the given
+// size, in pixel units.
+fn pixel_direction(x: f64, y: f64, width: usize, height: usize) -> Vec3 {
+    let theta = PI * y / height as f64;
+    let phi = (x / width as f64 - 0.5) * 2.0 * PI;
+
+    return Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+}
+
+// Coefficients of the Perez distribution function for one of Y, x and y.
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    fn new(a: f64, b: f64, c: f64, d: f64, e: f64) -> Perez {
+        return Perez { a, b, c, d, e };
+    }
+
+    // Relative value for a view direction at zenith angle theta, and gamma
+    // away from the sun.
+    fn evaluate(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+
+        return (1.0 + self.a * (self.b / cos_theta).exp()) * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma);
+    }
+}
+
+// The Preetham, Shirley and Smits (1999) model of a clear daylight sky,
+// together with a sun disk whose color depends on how much atmosphere the
+// sunlight passes through.
+pub struct Sky {
+    sun_direction: Vec3,
+    cos_sun_radius: f64,
+    sun_radiance: Color,
+    intensity: f64,
+    perez_luminance: Perez,
+    perez_x: Perez,
+    perez_y: Perez,
+    // Zenith values divided by the Perez function at the zenith.
+    zenith_luminance: f64,
+    zenith_x: f64,
+    zenith_y: f64,
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::new_default();
+    }
+
+    let cx = x * luminance / y;
+    let cz = (1.0 - x - y) * luminance / y;
+
+    let r = 3.2406 * cx - 1.5372 * luminance - 0.4986 * cz;
+    let g = -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz;
+    let b = 0.0557 * cx - 0.2040 * luminance + 1.0570 * cz;
+
+    return Color::new(r.max(0.0), g.max(0.0), b.max(0.0));
+}
+
+// Fraction of sunlight of the given wavelength, in micrometers, that passes
+// through the atmosphere, due to Rayleigh scattering and aerosols.
+fn sun_transmittance(wavelength: f64, optical_mass: f64, turbidity: f64) -> f64 {
+    let rayleigh = (-0.008735 * wavelength.powf(-4.08) * optical_mass).exp();
+    let beta = 0.04608 * turbidity - 0.04586;
+    let aerosol = (-beta * wavelength.powf(-1.3) * optical_mass).exp();
+
+    return rayleigh * aerosol;
+}
+
+impl Sky {
+    // The sun elevation is in degrees above the horizon and must be between
+    // 0 and 90. The azimuth is in degrees from the -z axis towards +x. A
+    // turbidity of 2 is a very clear sky, 10 a hazy one.
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, intensity: f64) -> Sky {
+        let elevation = degrees_to_radians(sun_elevation);
+        let azimuth = degrees_to_radians(sun_azimuth);
+        let sun_direction = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+
+        let t = turbidity;
+        let theta_s = PI / 2.0 - elevation;
+
+        let perez_luminance = Perez::new(0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703);
+        let perez_x = Perez::new(-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452);
+        let perez_y = Perez::new(-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let theta2 = theta_s * theta_s;
+        let theta3 = theta2 * theta_s;
+        let t2 = t * t;
+
+        let zenith_x = t2 * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta_s)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta_s + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = t2 * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta_s)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta_s + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta_s + 0.26688);
+
+        // Kasten's relative optical air mass, the length of the path through
+        // the atmosphere relative to the path straight up.
+        let optical_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let sun_radiance = SUN_RADIANCE * Color::new(
+            sun_transmittance(0.680, optical_mass, t),
+            sun_transmittance(0.550, optical_mass, t),
+            sun_transmittance(0.440, optical_mass, t),
+        );
+
+        return Sky {
+            sun_direction,
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+            sun_radiance,
+            intensity,
+            zenith_luminance: zenith_luminance / perez_luminance.evaluate(1.0, theta_s),
+            zenith_x: zenith_x / perez_x.evaluate(1.0, theta_s),
+            zenith_y: zenith_y / perez_y.evaluate(1.0, theta_s),
+            perez_luminance,
+            perez_x,
+            perez_y,
+        };
+    }
+
+    // Radiance of the sky alone. Directions below the horizon get the
+    // radiance of the horizon.
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        let d = unit_vector(direction);
+        let cos_theta = d.y().max(0.001);
+        let gamma = clamp(dot(d, self.sun_direction), -1.0, 1.0).acos();
+
+        let luminance = self.zenith_luminance * self.perez_luminance.evaluate(cos_theta, gamma);
+        let x = self.zenith_x * self.perez_x.evaluate(cos_theta, gamma);
+        let y = self.zenith_y * self.perez_y.evaluate(cos_theta, gamma);
+
+        return SKY_SCALE * xyy_to_rgb(x, y, luminance);
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let mut radiance = self.sky_radiance(direction);
+
+        if dot(unit_vector(direction), self.sun_direction) >= self.cos_sun_radius {
+            radiance += self.sun_radiance;
+        }
+
+        return self.intensity * radiance;
+    }
+
+    // An equirectangular image of the sky, in the layout of EnvironmentMap,
+    // for importance sampling. The sun is much smaller than a pixel, so each
+    // pixel it overlaps gets its radiance weighted by the covered fraction.
+    pub fn to_image(&self, width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+
+        for y in 0..height {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                let direction = pixel_direction(x as f64 + 0.5, y as f64 + 0.5, width, height);
+                *pixel = self.intensity * self.sky_radiance(direction);
+            }
+        }
+
+        let d = self.sun_direction;
+        let sun_theta = clamp(d.y(), -1.0, 1.0).acos();
+        let sun_phi = d.x().atan2(-d.z());
+
+        let row_height = PI / height as f64;
+        let column_width = 2.0 * PI / width as f64;
+        let first_row = ((sun_theta - SUN_ANGULAR_RADIUS) / row_height).floor().max(0.0) as usize;
+        let last_row = (((sun_theta + SUN_ANGULAR_RADIUS) / row_height) as usize).min(height - 1);
+
+        for y in first_row..=last_row {
+            // Near the poles the sun spans many columns.
+            let sin_theta = (y as f64 * row_height).sin().min(((y + 1) as f64 * row_height).sin()).max(1e-6);
+            let half_width = (SUN_ANGULAR_RADIUS / sin_theta).min(PI);
+            let center = (sun_phi / (2.0 * PI) + 0.5) * width as f64;
+            let first_column = (center - half_width / column_width).floor() as i64 - 1;
+            let last_column = (center + half_width / column_width).floor() as i64 + 1;
+            let column_count = ((last_column - first_column + 1) as usize).min(width);
+
+            for i in 0..column_count {
+                let x = (first_column + i as i64).rem_euclid(width as i64) as usize;
+                let mut covered = 0;
+
+                for sy in 0..SUN_SUBSAMPLES {
+                    for sx in 0..SUN_SUBSAMPLES {
+                        let px = x as f64 + (sx as f64 + 0.5) / SUN_SUBSAMPLES as f64;
+                        let py = y as f64 + (sy as f64 + 0.5) / SUN_SUBSAMPLES as f64;
+
+                        if dot(pixel_direction(px, py, width, height), self.sun_direction) >= self.cos_sun_radius {
+                            covered += 1;
+                        }
+                    }
+                }
+
+                let fraction = covered as f64 / (SUN_SUBSAMPLES * SUN_SUBSAMPLES) as f64;
+                image.row_mut(y)[x] += (self.intensity * fraction) * self.sun_radiance;
+            }
+        }
+
+        return image;
+    }
+}
+
+// A sky together with the map used to importance sample it.
+pub struct SkyBackground {
+    sky: Sky,
+    importance_map: EnvironmentMap,
+}
+
+impl SkyBackground {
+    pub fn new(sky: Sky) -> SkyBackground {
+        let importance_map = EnvironmentMap::new(sky.to_image(512, 256), 0.0, 1.0);
+
+        return SkyBackground {
+            sky,
+            importance_map,
+        };
+    }
+
+    pub fn sky(&self) -> &Sky {
+        return &self.sky;
+    }
+
+    pub fn importance_map(&self) -> &EnvironmentMap {
+        return &self.importance_map;
+    }
+}
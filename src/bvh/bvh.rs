@@ -0,0 +1,426 @@
+use super::BvhStats;
+
+use crate::aabb::*;
+use crate::math::*;
+
+const MAX_PRIMITIVES_PER_LEAF: usize = 4;
+const MAX_PRIMITIVES_PER_SAH_LEAF: usize = 16;
+const SAH_BIN_COUNT: usize = 12;
+const SAH_TRAVERSAL_COST: f64 = 1.0;
+const SAH_INTERSECTION_COST: f64 = 1.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SplitMethod {
+    // Splits at the middle of the centroid bounds along the longest axis.
+    Midpoint,
+    // Picks the cheapest of a fixed number of candidate splits along the
+    // longest axis according to the surface area heuristic.
+    Sah,
+}
+
+enum BvhNode {
+    Leaf {
+        bounding_box: Aabb,
+        first: usize,
+        count: usize,
+    },
+    Interior {
+        bounding_box: Aabb,
+        left: usize,
+        right: usize,
+        axis: i32,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct SahBin {
+    bounding_box: Option<Aabb>,
+    count: usize,
+}
+
+// A bounding volume hierarchy over a set of primitives identified by their
+// index into the slice of bounding boxes the hierarchy was built from. The
+// primitives themselves are owned by the caller, which is handed the index of
+// each candidate primitive during traversal.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    primitives: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(boxes: &[Aabb], split_method: SplitMethod) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            primitives: (0..boxes.len()).collect(),
+        };
+
+        if !boxes.is_empty() {
+            let centroids: Vec<Vec3> = boxes.iter().map(|b| b.centroid()).collect();
+            bvh.nodes.reserve(2 * boxes.len() / MAX_PRIMITIVES_PER_LEAF + 1);
+            bvh.build(boxes, &centroids, split_method, 0, boxes.len());
+        }
+
+        return bvh;
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        return match self.nodes.first() {
+            Some(BvhNode::Leaf { bounding_box, .. }) => Some(*bounding_box),
+            Some(BvhNode::Interior { bounding_box, .. }) => Some(*bounding_box),
+            None => None,
+        };
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats::new();
+
+        if !self.nodes.is_empty() {
+            self.gather_stats(0, 1, &mut stats);
+        }
+
+        return stats;
+    }
+
+    fn gather_stats(&self, node_index: usize, depth: usize, stats: &mut BvhStats) {
+        match &self.nodes[node_index] {
+            BvhNode::Leaf { count, .. } => stats.add_leaf(depth, *count),
+            BvhNode::Interior { left, right, .. } => {
+                stats.add_interior(depth);
+                self.gather_stats(*left, depth + 1, stats);
+                self.gather_stats(*right, depth + 1, stats);
+            },
+        }
+    }
+
+    fn build(&mut self, boxes: &[Aabb], centroids: &[Vec3], split_method: SplitMethod, start: usize, end: usize) -> usize {
+        let node_index = self.nodes.len();
+        let count = end - start;
+
+        let mut bounding_box = boxes[self.primitives[start]];
+        let mut centroid_box = Aabb::new(centroids[self.primitives[start]], centroids[self.primitives[start]]);
+
+        for &i in &self.primitives[start + 1..end] {
+            bounding_box = surrounding_box(&bounding_box, &boxes[i]);
+            centroid_box = surrounding_box(&centroid_box, &Aabb::new(centroids[i], centroids[i]));
+        }
+
+        self.nodes.push(BvhNode::Leaf { bounding_box, first: start, count });
+
+        if count == 1 || (split_method == SplitMethod::Midpoint && count <= MAX_PRIMITIVES_PER_LEAF) {
+            return node_index;
+        }
+
+        let axis = centroid_box.longest_axis();
+
+        // All centroids coincide, so no split can separate the primitives.
+        if centroid_box.extent()[axis] <= 0.0 {
+            return node_index;
+        }
+
+        let split = match split_method {
+            SplitMethod::Midpoint => self.split_midpoint(centroids, &centroid_box, axis, start, end),
+            SplitMethod::Sah => self.split_sah(boxes, centroids, &bounding_box, &centroid_box, axis, start, end),
+        };
+
+        let mut mid = match split {
+            Some(mid) => mid,
+            None => return node_index,
+        };
+
+        // Fall back to a median split if the chosen split left one side empty.
+        if mid == start || mid == end {
+            mid = start + count / 2;
+            self.primitives[start..end].select_nth_unstable_by(count / 2, |&a, &b| {
+                centroids[a][axis].partial_cmp(&centroids[b][axis]).unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+
+        let left = self.build(boxes, centroids, split_method, start, mid);
+        let right = self.build(boxes, centroids, split_method, mid, end);
+
+        self.nodes[node_index] = BvhNode::Interior { bounding_box, left, right, axis };
+
+        return node_index;
+    }
+
+    fn split_midpoint(&mut self, centroids: &[Vec3], centroid_box: &Aabb, axis: i32, start: usize, end: usize) -> Option<usize> {
+        let split_value = centroid_box.centroid()[axis];
+
+        return Some(start + partition(&mut self.primitives[start..end], |&i| centroids[i][axis] < split_value));
+    }
+
+    // Returns None if keeping the primitives in a single leaf is cheaper than
+    // any of the candidate splits.
+    #[allow(clippy::too_many_arguments)]
+    fn split_sah(&mut self, boxes: &[Aabb], centroids: &[Vec3], bounding_box: &Aabb, centroid_box: &Aabb, axis: i32, start: usize, end: usize) -> Option<usize> {
+        let count = end - start;
+        let axis_min = centroid_box.min()[axis];
+        let axis_extent = centroid_box.extent()[axis];
+
+        let bin_index = |i: usize| -> usize {
+            let b = (SAH_BIN_COUNT as f64 * (centroids[i][axis] - axis_min) / axis_extent) as usize;
+            return b.min(SAH_BIN_COUNT - 1);
+        };
+
+        let mut bins = [SahBin { bounding_box: None, count: 0 }; SAH_BIN_COUNT];
+
+        for &i in &self.primitives[start..end] {
+            let bin = &mut bins[bin_index(i)];
+            bin.count += 1;
+            bin.bounding_box = Some(match &bin.bounding_box {
+                Some(b) => surrounding_box(b, &boxes[i]),
+                None => boxes[i],
+            });
+        }
+
+        // Sweep from the right to get the area and count on the right side of
+        // every split plane, then sweep from the left to evaluate the cost.
+        let mut right_area = [0.0; SAH_BIN_COUNT];
+        let mut right_count = [0; SAH_BIN_COUNT];
+        let mut accumulated: Option<Aabb> = None;
+        let mut accumulated_count = 0;
+
+        for b in (1..SAH_BIN_COUNT).rev() {
+            accumulated = merge_boxes(accumulated, bins[b].bounding_box);
+            accumulated_count += bins[b].count;
+            right_area[b] = accumulated.map_or(0.0, |a| a.surface_area());
+            right_count[b] = accumulated_count;
+        }
+
+        let parent_area = bounding_box.surface_area();
+        let mut best_cost = f64::INFINITY;
+        let mut best_split = 0;
+        accumulated = None;
+        accumulated_count = 0;
+
+        for b in 1..SAH_BIN_COUNT {
+            accumulated = merge_boxes(accumulated, bins[b - 1].bounding_box);
+            accumulated_count += bins[b - 1].count;
+
+            if accumulated_count == 0 || right_count[b] == 0 {
+                continue;
+            }
+
+            let left_area = accumulated.map_or(0.0, |a| a.surface_area());
+            let cost = SAH_TRAVERSAL_COST + SAH_INTERSECTION_COST * (left_area * accumulated_count as f64 + right_area[b] * right_count[b] as f64) / parent_area;
+
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = b;
+            }
+        }
+
+        let leaf_cost = SAH_INTERSECTION_COST * count as f64;
+
+        if count <= MAX_PRIMITIVES_PER_SAH_LEAF && (best_split == 0 || best_cost >= leaf_cost) {
+            return None;
+        }
+
+        if best_split == 0 {
+            return Some(start);
+        }
+
+        return Some(start + partition(&mut self.primitives[start..end], |&i| bin_index(i) < best_split));
+    }
+
+    // Calls intersect_primitive(index, t_min, t_max) for every primitive whose
+    // bounding box is hit by the ray, shrinking t_max to the closest hit
+    // reported so far. Returns true if any primitive reported a hit.
+    pub fn intersect<F>(&self, r: &Ray, t_min: f64, t_max: f64, mut intersect_primitive: F) -> bool
+    where
+        F: FnMut(usize, f64, f64) -> Option<f64>,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            match &self.nodes[node_index] {
+                BvhNode::Leaf { bounding_box, first, count } => {
+                    if !bounding_box.intersect(r, t_min, closest_so_far) {
+                        continue;
+                    }
+
+                    for &i in &self.primitives[*first..*first + *count] {
+                        if let Some(t) = intersect_primitive(i, t_min, closest_so_far) {
+                            hit_anything = true;
+                            closest_so_far = t;
+                        }
+                    }
+                },
+                BvhNode::Interior { bounding_box, left, right, axis } => {
+                    if !bounding_box.intersect(r, t_min, closest_so_far) {
+                        continue;
+                    }
+
+                    // Visit the child nearest to the ray origin first.
+                    if r.direction()[*axis] < 0.0 {
+                        stack.push(*left);
+                        stack.push(*right);
+                    } else {
+                        stack.push(*right);
+                        stack.push(*left);
+                    }
+                },
+            }
+        }
+
+        return hit_anything;
+    }
+}
+
+fn merge_boxes(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    return match (a, b) {
+        (Some(a), Some(b)) => Some(surrounding_box(&a, &b)),
+        (Some(a), None) => Some(a),
+        (None, b) => b,
+    };
+}
+
+fn partition<T, F>(items: &mut [T], predicate: F) -> usize
+where
+    F: Fn(&T) -> bool,
+{
+    let mut first_false = 0;
+
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, first_false);
+            first_false += 1;
+        }
+    }
+
+    return first_false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::random::Sampler;
+
+    // The index of the sphere hit and the ray parameter of the hit.
+    type Hit = Option<(usize, f64)>;
+
+    // The closer intersection of a ray with a sphere in (t_min, t_max).
+    fn hit_sphere(center: Vec3, radius: f64, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let oc = r.origin() - center;
+        let a = r.direction().length_squared();
+        let half_b = dot(oc, r.direction());
+        let c = oc.length_squared() - radius * radius;
+        let discriminant = half_b * half_b - a * c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let roots = [(-half_b - discriminant.sqrt()) / a, (-half_b + discriminant.sqrt()) / a];
+
+        return roots.iter().copied().find(|&t| t > t_min && t < t_max);
+    }
+
+    fn sphere_box(center: Vec3, radius: f64) -> Aabb {
+        return Aabb::new(center - Vec3::new(radius, radius, radius), center + Vec3::new(radius, radius, radius));
+    }
+
+    // The closest hit through the hierarchy and by testing every sphere.
+    fn closest_hits(bvh: &Bvh, spheres: &[(Vec3, f64)], r: &Ray) -> (Hit, Hit) {
+        let mut bvh_hit = None;
+
+        bvh.intersect(r, 0.001, f64::INFINITY, |i, t_min, t_max| {
+            let t = hit_sphere(spheres[i].0, spheres[i].1, r, t_min, t_max)?;
+            bvh_hit = Some((i, t));
+            return Some(t);
+        });
+
+        let mut linear_hit: Hit = None;
+
+        for (i, &(center, radius)) in spheres.iter().enumerate() {
+            let t_max = linear_hit.map_or(f64::INFINITY, |(_, t)| t);
+
+            if let Some(t) = hit_sphere(center, radius, r, 0.001, t_max) {
+                linear_hit = Some((i, t));
+            }
+        }
+
+        return (bvh_hit, linear_hit);
+    }
+
+    fn random_point(sampler: &mut Sampler, min: f64, max: f64) -> Vec3 {
+        return Vec3::new(sampler.double_range(min, max), sampler.double_range(min, max), sampler.double_range(min, max));
+    }
+
+    #[test]
+    fn matches_linear_scan() {
+        let mut sampler = Sampler::new(7);
+        let spheres: Vec<(Vec3, f64)> = (0..500).map(|_| (random_point(&mut sampler, -10.0, 10.0), sampler.double_range(0.05, 1.0))).collect();
+        let boxes: Vec<Aabb> = spheres.iter().map(|&(center, radius)| sphere_box(center, radius)).collect();
+
+        for split_method in [SplitMethod::Midpoint, SplitMethod::Sah] {
+            let bvh = Bvh::new(&boxes, split_method);
+            let mut hits = 0;
+
+            for _ in 0..2000 {
+                let r = Ray::new(random_point(&mut sampler, -12.0, 12.0), random_point(&mut sampler, -1.0, 1.0), 0.0);
+                let (bvh_hit, linear_hit) = closest_hits(&bvh, &spheres, &r);
+
+                assert_eq!(bvh_hit.map(|(i, _)| i), linear_hit.map(|(i, _)| i));
+                hits += bvh_hit.is_some() as usize;
+            }
+
+            assert!(hits > 100);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let bvh = Bvh::new(&[], SplitMethod::Sah);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        assert!(bvh.bounding_box().is_none());
+        assert!(!bvh.intersect(&r, 0.0, f64::INFINITY, |_, _, _| panic!("no primitives to intersect")));
+    }
+
+    #[test]
+    fn single_box() {
+        let spheres = [(Vec3::new(0.0, 0.0, 5.0), 1.0)];
+
+        for split_method in [SplitMethod::Midpoint, SplitMethod::Sah] {
+            let bvh = Bvh::new(&[sphere_box(spheres[0].0, spheres[0].1)], split_method);
+            let bounding_box = bvh.bounding_box().unwrap();
+            assert_eq!(bounding_box.min().z(), 4.0);
+            assert_eq!(bounding_box.max().z(), 6.0);
+
+            let (bvh_hit, _) = closest_hits(&bvh, &spheres, &Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0));
+            assert_eq!(bvh_hit, Some((0, 4.0)));
+
+            let (bvh_hit, _) = closest_hits(&bvh, &spheres, &Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0));
+            assert_eq!(bvh_hit, None);
+        }
+    }
+
+    // Primitives that can't be told apart by their centroids still have to
+    // end up in leaves.
+    #[test]
+    fn equal_centroids() {
+        let spheres: Vec<(Vec3, f64)> = (1..=40).map(|i| (Vec3::new(1.0, 2.0, 3.0), i as f64 * 0.1)).collect();
+        let boxes: Vec<Aabb> = spheres.iter().map(|&(center, radius)| sphere_box(center, radius)).collect();
+        let mut sampler = Sampler::new(3);
+
+        for split_method in [SplitMethod::Midpoint, SplitMethod::Sah] {
+            let bvh = Bvh::new(&boxes, split_method);
+
+            for _ in 0..200 {
+                let r = Ray::new(random_point(&mut sampler, -10.0, 10.0), random_point(&mut sampler, -1.0, 1.0), 0.0);
+                let (bvh_hit, linear_hit) = closest_hits(&bvh, &spheres, &r);
+
+                assert_eq!(bvh_hit.map(|(i, _)| i), linear_hit.map(|(i, _)| i));
+            }
+        }
+    }
+}
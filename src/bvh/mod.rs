@@ -0,0 +1,6 @@
+#[allow(clippy::module_inception)]
+mod bvh;
+mod stats;
+
+pub use bvh::*;
+pub use stats::*;
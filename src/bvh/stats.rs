@@ -0,0 +1,81 @@
+use std::fmt;
+use std::fmt::Display;
+
+pub struct BvhStats {
+    node_count: usize,
+    leaf_count: usize,
+    max_depth: usize,
+    primitive_count: usize,
+    min_leaf_size: usize,
+    max_leaf_size: usize,
+}
+
+impl BvhStats {
+    pub fn new() -> BvhStats {
+        return BvhStats {
+            node_count: 0,
+            leaf_count: 0,
+            max_depth: 0,
+            primitive_count: 0,
+            min_leaf_size: 0,
+            max_leaf_size: 0,
+        };
+    }
+
+    pub fn add_interior(&mut self, depth: usize) {
+        self.node_count += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    pub fn add_leaf(&mut self, depth: usize, size: usize) {
+        self.min_leaf_size = if self.leaf_count == 0 { size } else { self.min_leaf_size.min(size) };
+        self.max_leaf_size = self.max_leaf_size.max(size);
+        self.node_count += 1;
+        self.leaf_count += 1;
+        self.primitive_count += size;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    pub fn node_count(&self) -> usize {
+        return self.node_count;
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        return self.leaf_count;
+    }
+
+    pub fn max_depth(&self) -> usize {
+        return self.max_depth;
+    }
+
+    pub fn min_leaf_size(&self) -> usize {
+        return self.min_leaf_size;
+    }
+
+    pub fn max_leaf_size(&self) -> usize {
+        return self.max_leaf_size;
+    }
+
+    pub fn average_leaf_size(&self) -> f64 {
+        if self.leaf_count == 0 {
+            return 0.0;
+        }
+
+        return self.primitive_count as f64 / self.leaf_count as f64;
+    }
+}
+
+impl Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leaves, depth {}, leaf size {}..{} (average {:.2})",
+            self.node_count(),
+            self.leaf_count(),
+            self.max_depth(),
+            self.min_leaf_size(),
+            self.max_leaf_size(),
+            self.average_leaf_size()
+        )
+    }
+}
@@ -0,0 +1,84 @@
+use crate::math::*;
+
+use crate::random::Sampler;
+
+fn random_in_unit_disk(sampler: &mut Sampler) -> Vec3 {
+    loop {
+        let p = Vec3::new(sampler.double_range(-1.0, 1.0), sampler.double_range(-1.0, 1.0), 0.0);
+
+        if p.length_squared() < 1.0 {
+            return p;
+        }
+    }
+}
+
+pub struct Camera {
+    origin: Vec3,
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    u: Vec3,
+    v: Vec3,
+    lens_radius: f64,
+    time0: f64,
+    time1: f64,
+}
+
+impl Camera {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64, time0: f64, time1: f64) -> Camera {
+        let theta = degrees_to_radians(vfov);
+        let h = (theta/2.0).tan();
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let w = unit_vector(look_from - look_at);
+        let u = unit_vector(cross(v_up, w));
+        let v = cross(w, u);
+
+        let origin = look_from;
+        let horizontal = focus_dist * viewport_width * u;
+        let vertical = focus_dist * viewport_height * v;
+        let lower_left_corner = origin - horizontal/2.0 - vertical/2.0 - focus_dist * w;
+
+        Camera {
+            origin,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            lens_radius: aperture / 2.0,
+            time0,
+            time1,
+        }
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut Sampler) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(sampler);
+        let offset = self.u * rd.x() + self.v * rd.y();
+
+        return Ray::new(self.origin + offset, self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin - offset, sampler.double_range(self.time0, self.time1));
+    }
+
+    pub fn time0(&self) -> f64 {
+        return self.time0;
+    }
+
+    pub fn time1(&self) -> f64 {
+        return self.time1;
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        return self.horizontal.length() / self.vertical.length();
+    }
+
+    // Widens or narrows the view to a new aspect ratio, keeping the vertical
+    // field of view.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        let center = self.lower_left_corner + self.horizontal/2.0 + self.vertical/2.0;
+
+        self.horizontal *= aspect_ratio / self.aspect_ratio();
+        self.lower_left_corner = center - self.horizontal/2.0 - self.vertical/2.0;
+    }
+}
@@ -0,0 +1,299 @@
+use crate::image::BitDepth;
+use crate::image::ExrCompression;
+use crate::image::ToneMapOperator;
+use crate::image::ImageFormat;
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+
+Renders a scene and writes the image as PPM to standard output, or to the
+file given with --output.
+
+Scene selection:
+  --scene <NAME|FILE>    Built-in scene (random, triangles, cornell) or JSON
+                         scene file [default: random]
+  --obj <FILE>           Render an OBJ model, framed automatically
+
+Render settings, overriding those of the scene:
+  --width <PIXELS>       Image width
+  --height <PIXELS>      Image height. If only one of width and height is
+                         given, the other follows from the camera's aspect ratio
+  --spp <COUNT>          Samples per pixel
+  --max-depth <COUNT>    Maximum number of bounces per path
+  --seed <NUMBER>        Seed of the random number generator
+
+Other options:
+  --threads <COUNT>      Number of worker threads [default: all cores]
+  --output <FILE>        Output file instead of standard output. The format is
+                         chosen by the extension: .ppm, .png, .exr, .hdr
+                         or .pfm
+  --bit-depth <8|16>     Bits per channel of PNG output [default: 8]
+  --exr-compression <none|zip>
+                         Compression of OpenEXR output [default: zip]
+  --tonemap <OPERATOR>   Tone mapping of .ppm and .png output: clamp, reinhard,
+                         reinhard-extended, aces or hable [default: clamp]
+  --exposure <STOPS>     Exposure adjustment before tone mapping [default: 0]
+  --white-point <L>      Luminance mapped to white by reinhard-extended
+                         [default: 4]
+  --compare <FILE>       Print the RMS error against a reference .exr, .hdr or
+                         .pfm image of the same size
+  --bench-bvh            Benchmark the BVH builders instead of rendering
+  -h, --help             Print this help
+";
+
+pub enum Command {
+    Render(Options),
+    BenchBvh,
+    Help,
+}
+
+pub struct Options {
+    scene: Option<String>,
+    obj: Option<PathBuf>,
+    width: Option<i32>,
+    height: Option<i32>,
+    samples_per_pixel: Option<i32>,
+    max_depth: Option<i32>,
+    seed: Option<u64>,
+    threads: Option<usize>,
+    output: Option<PathBuf>,
+    bit_depth: BitDepth,
+    exr_compression: ExrCompression,
+    compare: Option<PathBuf>,
+    tone_map_operator: ToneMapOperator,
+    exposure: f64,
+    white_point: f64,
+}
+
+impl Options {
+    fn new_default() -> Options {
+        return Options {
+            scene: None,
+            obj: None,
+            width: None,
+            height: None,
+            samples_per_pixel: None,
+            max_depth: None,
+            seed: None,
+            threads: None,
+            output: None,
+            bit_depth: BitDepth::Eight,
+            exr_compression: ExrCompression::Zip,
+            compare: None,
+            tone_map_operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        };
+    }
+
+    pub fn scene(&self) -> Option<&str> {
+        return self.scene.as_deref();
+    }
+
+    pub fn obj(&self) -> Option<&PathBuf> {
+        return self.obj.as_ref();
+    }
+
+    pub fn width(&self) -> Option<i32> {
+        return self.width;
+    }
+
+    pub fn height(&self) -> Option<i32> {
+        return self.height;
+    }
+
+    pub fn samples_per_pixel(&self) -> Option<i32> {
+        return self.samples_per_pixel;
+    }
+
+    pub fn max_depth(&self) -> Option<i32> {
+        return self.max_depth;
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        return self.seed;
+    }
+
+    pub fn threads(&self) -> Option<usize> {
+        return self.threads;
+    }
+
+    pub fn output(&self) -> Option<&PathBuf> {
+        return self.output.as_ref();
+    }
+
+    pub fn bit_depth(&self) -> BitDepth {
+        return self.bit_depth;
+    }
+
+    pub fn exr_compression(&self) -> ExrCompression {
+        return self.exr_compression;
+    }
+
+    pub fn compare(&self) -> Option<&PathBuf> {
+        return self.compare.as_ref();
+    }
+
+    pub fn tone_map_operator(&self) -> ToneMapOperator {
+        return self.tone_map_operator;
+    }
+
+    pub fn exposure(&self) -> f64 {
+        return self.exposure;
+    }
+
+    pub fn white_point(&self) -> f64 {
+        return self.white_point;
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    return value.parse::<T>().map_err(|_| format!("invalid value '{}' for {}", value, option));
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<i32, String> {
+    let n: i32 = parse_value(option, value)?;
+
+    if n < 1 {
+        return Err(format!("{} must be at least 1, found {}", option, n));
+    }
+
+    return Ok(n);
+}
+
+// Parses the command line arguments, not including the program name.
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut options = Options::new_default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--bench-bvh" => return Ok(Command::BenchBvh),
+            _ => {},
+        }
+
+        let value = match arg.as_str() {
+            "--scene" | "--obj" | "--width" | "--height" | "--spp" | "--max-depth" | "--seed" | "--threads" | "--output" | "--bit-depth" | "--exr-compression" | "--compare" | "--tonemap" | "--exposure" | "--white-point" => {
+                match args.next() {
+                    Some(value) => value,
+                    None => return Err(format!("{} needs a value", arg)),
+                }
+            },
+            _ => return Err(format!("unknown option '{}'", arg)),
+        };
+
+        match arg.as_str() {
+            "--scene" => options.scene = Some(value),
+            "--obj" => options.obj = Some(PathBuf::from(value)),
+            "--width" => options.width = Some(parse_positive(&arg, &value)?),
+            "--height" => options.height = Some(parse_positive(&arg, &value)?),
+            "--spp" => options.samples_per_pixel = Some(parse_positive(&arg, &value)?),
+            "--max-depth" => options.max_depth = Some(parse_positive(&arg, &value)?),
+            "--seed" => options.seed = Some(parse_value(&arg, &value)?),
+            "--threads" => options.threads = Some(parse_positive(&arg, &value)? as usize),
+            "--output" => {
+                let path = PathBuf::from(value);
+
+                if ImageFormat::from_path(&path).is_none() {
+                    return Err(format!("unsupported output format '{}', expected a .ppm, .png, .exr, .hdr or .pfm file", path.display()));
+                }
+
+                options.output = Some(path);
+            },
+            "--bit-depth" => {
+                options.bit_depth = match value.as_str() {
+                    "8" => BitDepth::Eight,
+                    "16" => BitDepth::Sixteen,
+                    _ => return Err(format!("--bit-depth must be 8 or 16, found '{}'", value)),
+                };
+            },
+            "--exr-compression" => {
+                options.exr_compression = match value.as_str() {
+                    "none" => ExrCompression::None,
+                    "zip" => ExrCompression::Zip,
+                    _ => return Err(format!("--exr-compression must be none or zip, found '{}'", value)),
+                };
+            },
+            "--compare" => {
+                let path = PathBuf::from(value);
+
+                // PNG images are tone mapped, so they can't be compared with
+                // the linear render.
+                if !ImageFormat::from_path(&path).is_some_and(|format| format.can_read() && format != ImageFormat::Png) {
+                    return Err(format!("unsupported reference image '{}', expected a .exr, .hdr or .pfm file", path.display()));
+                }
+
+                options.compare = Some(path);
+            },
+            "--tonemap" => {
+                options.tone_map_operator = match ToneMapOperator::from_name(&value) {
+                    Some(operator) => operator,
+                    None => return Err(format!("unknown tone mapping operator '{}'", value)),
+                };
+            },
+            "--exposure" => {
+                options.exposure = parse_value(&arg, &value)?;
+
+                if !options.exposure.is_finite() {
+                    return Err(format!("invalid value '{}' for {}", value, arg));
+                }
+            },
+            "--white-point" => {
+                options.white_point = parse_value(&arg, &value)?;
+
+                if !(options.white_point > 0.0 && options.white_point.is_finite()) {
+                    return Err(format!("{} must be a positive number, found '{}'", arg, value));
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    if options.scene.is_some() && options.obj.is_some() {
+        return Err("--scene and --obj can't be used together".to_string());
+    }
+
+    return Ok(Command::Render(options));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        return parse_args(args.iter().map(|arg| arg.to_string()));
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        return match parse(args) {
+            Ok(_) => panic!("{:?} parsed", args),
+            Err(message) => message,
+        };
+    }
+
+    #[test]
+    fn options() {
+        let options = match parse(&["--scene", "a.json", "--width", "1", "--height", "2", "--output", "a.png"]) {
+            Ok(Command::Render(options)) => options,
+            _ => panic!("not a render command"),
+        };
+
+        assert_eq!(options.scene(), Some("a.json"));
+        assert_eq!(options.width(), Some(1));
+        assert_eq!(options.height(), Some(2));
+        assert_eq!(options.output(), Some(&PathBuf::from("a.png")));
+        assert!(matches!(parse(&["--help"]), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_error(&["--sceen", "a.json"]), "unknown option '--sceen'");
+        assert_eq!(parse_error(&["--width"]), "--width needs a value");
+        assert_eq!(parse_error(&["--width", "0"]), "--width must be at least 1, found 0");
+        assert_eq!(parse_error(&["--width", "wide"]), "invalid value 'wide' for --width");
+        assert_eq!(parse_error(&["--scene", "a.json", "--obj", "a.obj"]), "--scene and --obj can't be used together");
+        assert!(parse_error(&["--output", "a.jpg"]).starts_with("unsupported output format 'a.jpg'"));
+    }
+}
@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+mod cli;
+
+pub use cli::*;
@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+// An error in a file the renderer reads, like a scene, a model or an image,
+// with the line where it was found for text formats.
+#[derive(Debug)]
+pub struct FileError {
+    path: PathBuf,
+    line: usize,
+    message: String,
+}
+
+impl FileError {
+    // An error at the given line of a file. Line 0 refers to the file as a
+    // whole, for example when it can't be read.
+    pub fn new(path: &Path, line: usize, message: String) -> FileError {
+        return FileError {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+    }
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+        }
+    }
+}
+
+impl Error for FileError {}
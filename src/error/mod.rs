@@ -0,0 +1,3 @@
+mod fileerror;
+
+pub use fileerror::*;
@@ -0,0 +1,264 @@
+use super::*;
+
+use crate::aabb::*;
+use crate::math::*;
+use crate::random::Sampler;
+
+use std::sync::Arc;
+
+// The largest rotation, in radians, between the times at which the bounding
+// box is evaluated.
+const BOUNDING_BOX_ANGLE_STEP: f64 = std::f64::consts::PI / 180.0;
+
+// A transform at one point in time, split so that it can be interpolated.
+struct Keyframe {
+    time: f64,
+    translation: Vec3,
+    rotation: Quat,
+    stretch: Mat4,
+    inverse_stretch: Mat4,
+}
+
+// Moves any geometry along keyframed transforms for motion blur. Between two
+// keyframes the translation and stretch are interpolated linearly and the
+// rotation through quaternions, so that objects turn about a fixed axis at
+// a constant rate. Before the first keyframe and after the last the
+// transform holds still.
+pub struct AnimatedTransform {
+    geometry: Arc<dyn Geometry>,
+    keyframes: Vec<Keyframe>,
+    // As in Transform, if every keyframe is a similarity with the same
+    // scale, so that the area doesn't change over time.
+    area_scale: Option<f64>,
+}
+
+impl AnimatedTransform {
+    // Takes (time, object to world) pairs in increasing order of time. None
+    // if there are none or a matrix can't be inverted.
+    pub fn new(geometry: Arc<dyn Geometry>, keyframes: Vec<(f64, Mat4)>) -> Option<AnimatedTransform> {
+        if keyframes.is_empty() {
+            return None;
+        }
+
+        let mut area_scale = similarity_area_scale(&keyframes[0].1);
+
+        for (_, object_to_world) in &keyframes {
+            if let (Some(scale), Some(keyframe_scale)) = (area_scale, similarity_area_scale(object_to_world)) {
+                if (scale - keyframe_scale).abs() <= 1e-9 * scale {
+                    continue;
+                }
+            }
+
+            area_scale = None;
+        }
+
+        let keyframes = keyframes.into_iter().map(|(time, object_to_world)| {
+            let (translation, rotation, stretch) = object_to_world.decompose()?;
+            let inverse_stretch = stretch.linear_inverse()?;
+
+            return Some(Keyframe { time, translation, rotation: Quat::from_rotation(&rotation), stretch, inverse_stretch });
+        }).collect::<Option<Vec<Keyframe>>>()?;
+
+        return Some(AnimatedTransform {
+            geometry,
+            keyframes,
+            area_scale,
+        });
+    }
+
+    // The translation, rotation and stretch at a time, and the inverse of
+    // the stretch, which is None where the stretch passes through a singular
+    // matrix between keyframes.
+    fn parts_at(&self, time: f64) -> (Vec3, Quat, Mat4, Option<Mat4>) {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+
+        if time <= first.time {
+            return (first.translation, first.rotation, first.stretch, Some(first.inverse_stretch));
+        }
+
+        if time >= last.time {
+            return (last.translation, last.rotation, last.stretch, Some(last.inverse_stretch));
+        }
+
+        let i = self.keyframes.partition_point(|keyframe| keyframe.time <= time) - 1;
+        let (a, b) = (&self.keyframes[i], &self.keyframes[i + 1]);
+        let t = (time - a.time) / (b.time - a.time);
+        let stretch = a.stretch.lerp(&b.stretch, t);
+
+        return ((1.0 - t) * a.translation + t * b.translation, a.rotation.slerp(&b.rotation, t), stretch, stretch.linear_inverse());
+    }
+
+    fn object_to_world(&self, time: f64) -> Mat4 {
+        let (translation, rotation, stretch, _) = self.parts_at(time);
+        return Mat4::translation(translation) * rotation.to_rotation() * stretch;
+    }
+
+    // The object to world transform at a time and its inverse, which is
+    // composed from the inverted parts rather than found by a general matrix
+    // inverse.
+    fn transforms_at(&self, time: f64) -> Option<(Mat4, Mat4)> {
+        let (translation, rotation, stretch, inverse_stretch) = self.parts_at(time);
+        let rotation = rotation.to_rotation();
+
+        let object_to_world = Mat4::translation(translation) * rotation * stretch;
+        let world_to_object = inverse_stretch? * rotation.transpose() * Mat4::translation(-translation);
+
+        return Some((object_to_world, world_to_object));
+    }
+}
+
+impl Geometry for AnimatedTransform {
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // The stretch can pass through zero between keyframes.
+        let (object_to_world, world_to_object) = match self.transforms_at(r.time()) {
+            Some(transforms) => transforms,
+            None => return false,
+        };
+
+        let object_ray = Ray::new(world_to_object.transform_point(r.origin()), world_to_object.transform_vector(r.direction()), r.time());
+
+        if !self.geometry.intersect(&object_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        let position = object_to_world.transform_point(rec.position());
+        let normal_to_world = world_to_object.transpose();
+        let normal = unit_vector(normal_to_world.transform_vector(rec.normal()));
+        let geometric_normal = unit_vector(normal_to_world.transform_vector(rec.geometric_normal()));
+        rec.set_position_and_normals(position, normal, geometric_normal);
+
+        return true;
+    }
+
+    // The interval is split at the keyframes, and further into steps over
+    // which the rotation is small. Within a step, translation and stretch
+    // move the corners of the object's box along straight lines, and the
+    // rotation by d radians moves a point at distance r from the origin at
+    // most d * r / 2 away from them, so the boxes around the corners at the
+    // ends of each step are padded by that much.
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        let mut object_box = Aabb::new_default();
+
+        if !self.geometry.bounding_box(t0, t1, &mut object_box) {
+            return false;
+        }
+
+        let corners = box_corners(&object_box);
+
+        let mut times = vec![t0];
+        times.extend(self.keyframes.iter().map(|keyframe| keyframe.time).filter(|&time| time > t0 && time < t1));
+        times.push(t1);
+
+        let mut result: Option<Aabb> = None;
+
+        for interval in times.windows(2) {
+            let angle = self.parts_at(interval[0]).1.angle_to(&self.parts_at(interval[1]).1);
+            let steps = ((angle / BOUNDING_BOX_ANGLE_STEP).ceil() as usize).max(1);
+
+            for step in 0..steps {
+                let times = [
+                    interval[0] + (interval[1] - interval[0]) * step as f64 / steps as f64,
+                    interval[0] + (interval[1] - interval[0]) * (step + 1) as f64 / steps as f64,
+                ];
+
+                let mut radius: f64 = 0.0;
+                let mut step_box: Option<Aabb> = None;
+
+                for time in times {
+                    let (_, _, stretch, _) = self.parts_at(time);
+                    let time_box = transform_bounding_box(&self.object_to_world(time), &object_box);
+
+                    radius = corners.iter().map(|&corner| stretch.transform_vector(corner).length()).fold(radius, f64::max);
+
+                    step_box = Some(match step_box {
+                        Some(step_box) => surrounding_box(&step_box, &time_box),
+                        None => time_box,
+                    });
+                }
+
+                let step_box = step_box.unwrap();
+                let pad = Vec3::new(1.0, 1.0, 1.0) * (0.5 * radius * angle / steps as f64);
+                let step_box = Aabb::new(step_box.min() - pad, step_box.max() + pad);
+
+                result = Some(match result {
+                    Some(result) => surrounding_box(&result, &step_box),
+                    None => step_box,
+                });
+            }
+        }
+
+        *output_box = result.unwrap();
+
+        return true;
+    }
+
+    fn area(&self, material_id: usize) -> f64 {
+        return match self.area_scale {
+            Some(scale) => scale * self.geometry.area(material_id),
+            None => 0.0,
+        };
+    }
+
+    fn sample_surface(&self, material_id: usize, time: f64, sampler: &mut Sampler) -> Option<SurfaceSample> {
+        self.area_scale?;
+
+        let sample = self.geometry.sample_surface(material_id, time, sampler)?;
+        let (object_to_world, world_to_object) = self.transforms_at(time)?;
+        let position = object_to_world.transform_point(sample.position());
+        let normal = unit_vector(world_to_object.transpose().transform_vector(sample.normal()));
+
+        return Some(SurfaceSample::new(position, normal));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_transform() -> AnimatedTransform {
+        let sphere = Arc::new(Sphere::new_stationary(Vec3::new_default(), 1.0));
+        let keyframes = vec![
+            (0.0, Mat4::scaling(Vec3::new(1.0, 2.0, 1.0))),
+            (1.0, Mat4::translation(Vec3::new(10.0, 0.0, 0.0)) * Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 2.0) * Mat4::scaling(Vec3::new(3.0, 1.0, 0.5))),
+        ];
+
+        return AnimatedTransform::new(sphere, keyframes).unwrap();
+    }
+
+    #[test]
+    fn no_keyframes() {
+        assert!(AnimatedTransform::new(Arc::new(Sphere::new_stationary(Vec3::new_default(), 1.0)), Vec::new()).is_none());
+    }
+
+    #[test]
+    fn composed_inverse() {
+        let transform = test_transform();
+
+        for step in 0..=20 {
+            let time = -0.5 + 0.1 * step as f64;
+            let (object_to_world, world_to_object) = transform.transforms_at(time).unwrap();
+            let product = world_to_object * object_to_world;
+
+            for i in 0..4 {
+                for j in 0..4 {
+                    assert!((product.get(i, j) - if i == j { 1.0 } else { 0.0 }).abs() < 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hits_the_moved_object() {
+        let transform = test_transform();
+        let mut rec = HitRecord::new_default();
+        let (object_to_world, _) = transform.transforms_at(0.5).unwrap();
+        let center = object_to_world.transform_point(Vec3::new_default());
+
+        let r = Ray::new(center + Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.5);
+        assert!(transform.intersect(&r, 0.001, f64::INFINITY, &mut rec));
+
+        let r = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 1.0);
+        assert!(!transform.intersect(&r, 0.001, f64::INFINITY, &mut rec));
+    }
+}
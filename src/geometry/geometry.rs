@@ -1,9 +1,23 @@
 use super::HitRecord;
+use super::SurfaceSample;
 
 use crate::aabb::Aabb;
 use crate::math::Ray;
+use crate::random::Sampler;
 
-pub trait Geometry {
+pub trait Geometry: Send + Sync {
     fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool;
+
+    // Surface area of the parts with the given material id. Geometry that
+    // can't be sampled has no area, and can't be used as a light.
+    fn area(&self, _material_id: usize) -> f64 {
+        return 0.0;
+    }
+
+    // Picks a point uniformly by area on the parts with the given material
+    // id, at the given time.
+    fn sample_surface(&self, _material_id: usize, _time: f64, _sampler: &mut Sampler) -> Option<SurfaceSample> {
+        return None;
+    }
 }
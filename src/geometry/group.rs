@@ -0,0 +1,171 @@
+use super::*;
+
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::math::*;
+use crate::random::Sampler;
+
+use std::sync::Arc;
+
+// A part of a Group, which uses the group's material ids from first_material_id
+// on. Ids the part doesn't have materials for use its first one, as in an
+// entity.
+struct GroupPart {
+    geometry: Arc<dyn Geometry>,
+    first_material_id: usize,
+    material_count: usize,
+}
+
+// Several pieces of geometry with their own bounding volume hierarchy, so
+// that they can be placed by a single transform and added to a World as a
+// single entity. Each part keeps its own materials by getting its own range
+// of material ids.
+pub struct Group {
+    parts: Vec<GroupPart>,
+    bvh: Bvh,
+    bvh_parts: Vec<usize>,
+    unbounded_parts: Vec<usize>,
+}
+
+impl Group {
+    // Takes the parts with the number of materials each of them uses. The
+    // hierarchy bounds the parts over the shutter interval [time0, time1].
+    pub fn new(parts: Vec<(Arc<dyn Geometry>, usize)>, time0: f64, time1: f64) -> Group {
+        let mut boxes = Vec::new();
+        let mut bvh_parts = Vec::new();
+        let mut unbounded_parts = Vec::new();
+        let mut first_material_id = 0;
+
+        let parts: Vec<GroupPart> = parts.into_iter().map(|(geometry, material_count)| {
+            let part = GroupPart { geometry, first_material_id, material_count: material_count.max(1) };
+            first_material_id += part.material_count;
+            return part;
+        }).collect();
+
+        for (i, part) in parts.iter().enumerate() {
+            let mut output_box = Aabb::new_default();
+
+            if part.geometry.bounding_box(time0, time1, &mut output_box) {
+                boxes.push(output_box);
+                bvh_parts.push(i);
+            } else {
+                unbounded_parts.push(i);
+            }
+        }
+
+        let bvh = Bvh::new(&boxes, SplitMethod::Sah);
+
+        return Group { parts, bvh, bvh_parts, unbounded_parts };
+    }
+
+    // The part using a material id of the group, and the part's own id for it.
+    fn part_for(&self, material_id: usize) -> Option<(&GroupPart, usize)> {
+        let part = self.parts.iter().find(|part| material_id >= part.first_material_id && material_id < part.first_material_id + part.material_count)?;
+        return Some((part, material_id - part.first_material_id));
+    }
+}
+
+impl Geometry for Group {
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut closest_so_far = t_max;
+        let mut hit_part = None;
+
+        for &i in &self.unbounded_parts {
+            if self.parts[i].geometry.intersect(r, t_min, closest_so_far, rec) {
+                hit_part = Some(i);
+                closest_so_far = rec.t();
+            }
+        }
+
+        self.bvh.intersect(r, t_min, closest_so_far, |i, t_min, t_max| {
+            if self.parts[self.bvh_parts[i]].geometry.intersect(r, t_min, t_max, rec) {
+                hit_part = Some(self.bvh_parts[i]);
+                return Some(rec.t());
+            }
+
+            return None;
+        });
+
+        let part = match hit_part {
+            Some(i) => &self.parts[i],
+            None => return false,
+        };
+
+        let material_id = if rec.material_id() < part.material_count { rec.material_id() } else { 0 };
+        rec.set_material_id(part.first_material_id + material_id);
+
+        return true;
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
+        if !self.unbounded_parts.is_empty() {
+            return false;
+        }
+
+        return match self.bvh.bounding_box() {
+            Some(bounding_box) => {
+                *output_box = bounding_box;
+                true
+            },
+            None => false,
+        };
+    }
+
+    fn area(&self, material_id: usize) -> f64 {
+        return match self.part_for(material_id) {
+            Some((part, material_id)) => part.geometry.area(material_id),
+            None => 0.0,
+        };
+    }
+
+    fn sample_surface(&self, material_id: usize, time: f64, sampler: &mut Sampler) -> Option<SurfaceSample> {
+        let (part, material_id) = self.part_for(material_id)?;
+        return part.geometry.sample_surface(material_id, time, sampler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_group() -> Group {
+        let parts: Vec<(Arc<dyn Geometry>, usize)> = vec![
+            (Arc::new(Sphere::new_stationary(Vec3::new(0.0, 0.0, 0.0), 1.0)), 1),
+            (Arc::new(Sphere::new_stationary(Vec3::new(5.0, 0.0, 0.0), 2.0)), 1),
+        ];
+
+        return Group::new(parts, 0.0, 1.0);
+    }
+
+    #[test]
+    fn parts_keep_their_materials() {
+        let group = test_group();
+        let mut rec = HitRecord::new_default();
+
+        assert!(group.intersect(&Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0), 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.material_id(), 0);
+
+        assert!(group.intersect(&Ray::new(Vec3::new(5.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0), 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.material_id(), 1);
+        assert!((rec.t() - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn closest_part_wins() {
+        let group = test_group();
+        let mut rec = HitRecord::new_default();
+
+        assert!(group.intersect(&Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0), 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.material_id(), 0);
+        assert!((rec.t() - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn area_by_material() {
+        let group = test_group();
+
+        assert!((group.area(0) - 4.0 * std::f64::consts::PI).abs() < 1e-9);
+        assert!((group.area(1) - 16.0 * std::f64::consts::PI).abs() < 1e-9);
+        assert_eq!(group.area(2), 0.0);
+    }
+}
@@ -2,27 +2,46 @@ use crate::math::*;
 
 pub struct HitRecord {
     position: Vec3,
+    // The hit point in the space of the geometry that was hit, before any
+    // transforms moved it, so that solid textures move with the object.
+    object_position: Vec3,
     normal: Vec3,
+    // The normal of the surface itself, on the side that was hit, which the
+    // shading normal doesn't replace.
+    geometric_normal: Vec3,
     t: f64,
     front_face: bool,
+    barycentric: (f64, f64),
+    uv: (f64, f64),
+    material_id: usize,
 }
 
 impl HitRecord {
     pub fn new(position: Vec3, normal: Vec3, t: f64, front_face: bool) -> HitRecord {
         return HitRecord {
             position,
+            object_position: position,
             normal,
+            geometric_normal: normal,
             t,
             front_face,
+            barycentric: (0.0, 0.0),
+            uv: (0.0, 0.0),
+            material_id: 0,
         };
     }
 
     pub fn new_default() -> HitRecord {
         return HitRecord {
             position: Vec3::new_default(),
+            object_position: Vec3::new_default(),
             normal: Vec3::new_default(),
+            geometric_normal: Vec3::new_default(),
             t: 0.0,
             front_face: false,
+            barycentric: (0.0, 0.0),
+            uv: (0.0, 0.0),
+            material_id: 0,
         };
     }
 
@@ -30,10 +49,18 @@ impl HitRecord {
         return self.position;
     }
 
+    pub fn object_position(&self) -> Vec3 {
+        return self.object_position;
+    }
+
     pub fn normal(&self) -> Vec3 {
         return self.normal;
     }
 
+    pub fn geometric_normal(&self) -> Vec3 {
+        return self.geometric_normal;
+    }
+
     pub fn t(&self) -> f64 {
         return self.t;
     }
@@ -42,8 +69,54 @@ impl HitRecord {
         return self.front_face;
     }
 
+    // The barycentric coordinates (b1, b2) of the hit point, as weights of the
+    // second and third vertex of the triangle that was hit. The weight of the
+    // first vertex is 1 - b1 - b2.
+    pub fn barycentric(&self) -> (f64, f64) {
+        return self.barycentric;
+    }
+
+    pub fn set_barycentric(&mut self, b1: f64, b2: f64) {
+        self.barycentric = (b1, b2);
+    }
+
+    // Surface parameterization of the hit point, for texture lookups.
+    pub fn uv(&self) -> (f64, f64) {
+        return self.uv;
+    }
+
+    pub fn set_uv(&mut self, u: f64, v: f64) {
+        self.uv = (u, v);
+    }
+
+    // Index of the material to use for the hit point, for geometry made up of
+    // parts with different materials.
+    pub fn material_id(&self) -> usize {
+        return self.material_id;
+    }
+
+    pub fn set_material_id(&mut self, material_id: usize) {
+        self.material_id = material_id;
+    }
+
+    // Moves the hit into another space, for geometry placed by a transform.
+    // The normals must already face the side that was hit. The object
+    // position stays where the geometry itself put it.
+    pub fn set_position_and_normals(&mut self, position: Vec3, normal: Vec3, geometric_normal: Vec3) {
+        self.position = position;
+        self.normal = normal;
+        self.geometric_normal = geometric_normal;
+    }
+
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         self.front_face = dot(r.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face { outward_normal } else { -outward_normal };
+        self.geometric_normal = self.normal;
+    }
+
+    // Replaces the normal with a shading normal, flipped to the side of the
+    // surface that was hit. Must be called after set_face_normal.
+    pub fn set_shading_normal(&mut self, outward_normal: Vec3) {
+        self.normal = if self.front_face { outward_normal } else { -outward_normal };
     }
 }
@@ -1,7 +1,20 @@
+mod animatedtransform;
+#[allow(clippy::module_inception)]
 mod geometry;
+mod group;
 mod hitrecord;
 mod sphere;
+mod surfacesample;
+mod transform;
+mod triangle;
+mod trianglemesh;
 
+pub use animatedtransform::*;
 pub use geometry::*;
+pub use group::*;
 pub use hitrecord::*;
-pub use sphere::*;
\ No newline at end of file
+pub use sphere::*;
+pub use surfacesample::*;
+pub use transform::*;
+pub use triangle::*;
+pub use trianglemesh::*;
@@ -2,6 +2,19 @@ use super::*;
 
 use crate::aabb::*;
 use crate::math::*;
+use crate::random::Sampler;
+use crate::random_unit_vector;
+
+use std::f64::consts::PI;
+
+// Surface coordinates of a point on the unit sphere: u goes around the y
+// axis starting at -x, and v from the bottom pole to the top one.
+fn sphere_uv(p: Vec3) -> (f64, f64) {
+    let theta = clamp(-p.y(), -1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+
+    return (phi / (2.0 * PI), theta / PI);
+}
 
 pub struct Sphere {
     center0: Vec3,
@@ -51,6 +64,9 @@ impl Geometry for Sphere {
                 let outward_normal = (rec.position() - self.center(r.time())) / self.radius;
                 rec.set_face_normal(r, outward_normal);
 
+                let (u, v) = sphere_uv(outward_normal);
+                rec.set_uv(u, v);
+
                 return true;
             }
 
@@ -70,6 +86,9 @@ impl Geometry for Sphere {
                 let outward_normal = (rec.position() - self.center(r.time())) / self.radius;
                 rec.set_face_normal(r, outward_normal);
 
+                let (u, v) = sphere_uv(outward_normal);
+                rec.set_uv(u, v);
+
                 return true;
             }
         }
@@ -90,4 +109,23 @@ impl Geometry for Sphere {
 
         return true;
     }
+
+    // A sphere has a single material, with id zero.
+    fn area(&self, material_id: usize) -> f64 {
+        if material_id != 0 {
+            return 0.0;
+        }
+
+        return 4.0 * PI * self.radius * self.radius;
+    }
+
+    fn sample_surface(&self, material_id: usize, time: f64, sampler: &mut Sampler) -> Option<SurfaceSample> {
+        if material_id != 0 {
+            return None;
+        }
+
+        let normal = random_unit_vector(sampler);
+
+        return Some(SurfaceSample::new(self.center(time) + self.radius * normal, normal));
+    }
 }
@@ -0,0 +1,21 @@
+use crate::math::*;
+
+// A point on the surface of a geometry, chosen to send a shadow ray to.
+pub struct SurfaceSample {
+    position: Vec3,
+    normal: Vec3,
+}
+
+impl SurfaceSample {
+    pub fn new(position: Vec3, normal: Vec3) -> SurfaceSample {
+        return SurfaceSample { position, normal };
+    }
+
+    pub fn position(&self) -> Vec3 {
+        return self.position;
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        return self.normal;
+    }
+}
@@ -0,0 +1,151 @@
+use super::*;
+
+use crate::aabb::*;
+use crate::math::*;
+use crate::random::Sampler;
+
+use std::sync::Arc;
+
+// Places any geometry in the world with an affine transform. Rays are moved
+// into the space of the geometry instead of moving the geometry, so the
+// geometry and its acceleration structure stay as they are, and can be
+// shared by many instances.
+pub struct Transform {
+    geometry: Arc<dyn Geometry>,
+    object_to_world: Mat4,
+    world_to_object: Mat4,
+    // The inverse transpose, which keeps normals perpendicular to the
+    // surface under non-uniform scaling.
+    normal_to_world: Mat4,
+    // The factor by which areas grow, if the transform scales all
+    // directions equally. Otherwise areas don't grow uniformly and the
+    // geometry can't be sampled.
+    area_scale: Option<f64>,
+}
+
+// The factor by which the transform scales areas, if it is a similarity: a
+// rotation, reflection and uniform scaling, plus a translation.
+pub fn similarity_area_scale(m: &Mat4) -> Option<f64> {
+    let columns = [
+        m.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+        m.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+        m.transform_vector(Vec3::new(0.0, 0.0, 1.0)),
+    ];
+
+    let scale = columns[0].length_squared();
+    let tolerance = 1e-9 * scale;
+
+    for i in 0..3 {
+        if (columns[i].length_squared() - scale).abs() > tolerance || dot(columns[i], columns[(i + 1) % 3]).abs() > tolerance {
+            return None;
+        }
+    }
+
+    return Some(scale);
+}
+
+impl Transform {
+    // None if the matrix can't be inverted.
+    pub fn new(geometry: Arc<dyn Geometry>, object_to_world: Mat4) -> Option<Transform> {
+        let world_to_object = object_to_world.inverse()?;
+
+        return Some(Transform {
+            geometry,
+            object_to_world,
+            world_to_object,
+            normal_to_world: world_to_object.transpose(),
+            area_scale: similarity_area_scale(&object_to_world),
+        });
+    }
+}
+
+pub fn box_corners(bounding_box: &Aabb) -> [Vec3; 8] {
+    return std::array::from_fn(|i| Vec3::new(
+        if i & 1 == 0 { bounding_box.min().x() } else { bounding_box.max().x() },
+        if i & 2 == 0 { bounding_box.min().y() } else { bounding_box.max().y() },
+        if i & 4 == 0 { bounding_box.min().z() } else { bounding_box.max().z() },
+    ));
+}
+
+// The box around the transformed corners of a box.
+pub fn transform_bounding_box(m: &Mat4, bounding_box: &Aabb) -> Aabb {
+    let mut result: Option<Aabb> = None;
+
+    for corner in box_corners(bounding_box) {
+        let p = m.transform_point(corner);
+        let corner_box = Aabb::new(p, p);
+
+        result = Some(match result {
+            Some(result) => surrounding_box(&result, &corner_box),
+            None => corner_box,
+        });
+    }
+
+    return result.unwrap();
+}
+
+impl Geometry for Transform {
+    // The direction isn't normalized in object space, so that t is the same
+    // in both spaces.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let object_ray = Ray::new(self.world_to_object.transform_point(r.origin()), self.world_to_object.transform_vector(r.direction()), r.time());
+
+        if !self.geometry.intersect(&object_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        let position = self.object_to_world.transform_point(rec.position());
+        let normal = unit_vector(self.normal_to_world.transform_vector(rec.normal()));
+        let geometric_normal = unit_vector(self.normal_to_world.transform_vector(rec.geometric_normal()));
+        rec.set_position_and_normals(position, normal, geometric_normal);
+
+        return true;
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        let mut object_box = Aabb::new_default();
+
+        if !self.geometry.bounding_box(t0, t1, &mut object_box) {
+            return false;
+        }
+
+        *output_box = transform_bounding_box(&self.object_to_world, &object_box);
+
+        return true;
+    }
+
+    fn area(&self, material_id: usize) -> f64 {
+        return match self.area_scale {
+            Some(scale) => scale * self.geometry.area(material_id),
+            None => 0.0,
+        };
+    }
+
+    fn sample_surface(&self, material_id: usize, time: f64, sampler: &mut Sampler) -> Option<SurfaceSample> {
+        self.area_scale?;
+
+        let sample = self.geometry.sample_surface(material_id, time, sampler)?;
+        let position = self.object_to_world.transform_point(sample.position());
+        let normal = unit_vector(self.normal_to_world.transform_vector(sample.normal()));
+
+        return Some(SurfaceSample::new(position, normal));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_object_position() {
+        let sphere = Arc::new(Sphere::new_stationary(Vec3::new_default(), 1.0));
+        let transform = Transform::new(sphere, Mat4::translation(Vec3::new(5.0, 0.0, 0.0)) * Mat4::scaling(Vec3::new(2.0, 2.0, 2.0))).unwrap();
+        let mut rec = HitRecord::new_default();
+
+        let r = Ray::new(Vec3::new(5.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(transform.intersect(&r, 0.001, f64::INFINITY, &mut rec));
+
+        assert!((rec.position() - Vec3::new(5.0, 0.0, 2.0)).length() < 1e-9);
+        assert!((rec.object_position() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+    }
+}
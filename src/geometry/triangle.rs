@@ -0,0 +1,243 @@
+use super::*;
+
+use crate::aabb::*;
+use crate::math::*;
+use crate::random::Sampler;
+
+// Bounding boxes of triangles lying in an axis aligned plane are padded by
+// this much, since a box with zero thickness is never hit.
+const BOUNDING_BOX_PADDING: f64 = 0.0001;
+
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3) -> Triangle {
+        return Triangle{ vertices: [v0, v1, v2], normals: None };
+    }
+
+    // A triangle whose shading normal is interpolated from the given vertex
+    // normals, for smooth shading of curved surfaces.
+    pub fn new_with_normals(v0: Vec3, v1: Vec3, v2: Vec3, n0: Vec3, n1: Vec3, n2: Vec3) -> Triangle {
+        return Triangle{ vertices: [v0, v1, v2], normals: Some([n0, n1, n2]) };
+    }
+}
+
+impl Geometry for Triangle {
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let [v0, v1, v2] = self.vertices;
+
+        let (t, b0, b1, b2) = match intersect_triangle(r, v0, v1, v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let mut outward_normal = unit_vector(cross(v1 - v0, v2 - v0));
+        let shading_normal = self.normals.map(|[n0, n1, n2]| unit_vector(b0 * n0 + b1 * n1 + b2 * n2));
+
+        // With vertex normals, the side the normals point to is the outside,
+        // regardless of the winding of the vertices.
+        if let Some(shading_normal) = shading_normal {
+            if dot(outward_normal, shading_normal) < 0.0 {
+                outward_normal = -outward_normal;
+            }
+        }
+
+        *rec = HitRecord::new(r.at(t), outward_normal, t, false);
+        rec.set_face_normal(r, outward_normal);
+        rec.set_barycentric(b1, b2);
+
+        if let Some(shading_normal) = shading_normal {
+            rec.set_shading_normal(shading_normal);
+        }
+
+        // Without texture coordinates the triangle is parameterized by its
+        // barycentric coordinates, as in a mesh.
+        let (u, v) = rec.barycentric();
+        rec.set_uv(u, v);
+
+        return true;
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = triangle_bounding_box(self.vertices[0], self.vertices[1], self.vertices[2]);
+
+        return true;
+    }
+
+    fn area(&self, material_id: usize) -> f64 {
+        if material_id != 0 {
+            return 0.0;
+        }
+
+        let [v0, v1, v2] = self.vertices;
+
+        return triangle_area(v0, v1, v2);
+    }
+
+    fn sample_surface(&self, material_id: usize, _time: f64, sampler: &mut Sampler) -> Option<SurfaceSample> {
+        if material_id != 0 {
+            return None;
+        }
+
+        let [v0, v1, v2] = self.vertices;
+
+        return Some(sample_triangle(v0, v1, v2, sampler));
+    }
+}
+
+pub fn triangle_bounding_box(v0: Vec3, v1: Vec3, v2: Vec3) -> Aabb {
+    let mut min = Vec3::new(
+        v0.x().min(v1.x()).min(v2.x()),
+        v0.y().min(v1.y()).min(v2.y()),
+        v0.z().min(v1.z()).min(v2.z()),
+    );
+
+    let mut max = Vec3::new(
+        v0.x().max(v1.x()).max(v2.x()),
+        v0.y().max(v1.y()).max(v2.y()),
+        v0.z().max(v1.z()).max(v2.z()),
+    );
+
+    let padding = Vec3::new(
+        if max.x() - min.x() < BOUNDING_BOX_PADDING { BOUNDING_BOX_PADDING } else { 0.0 },
+        if max.y() - min.y() < BOUNDING_BOX_PADDING { BOUNDING_BOX_PADDING } else { 0.0 },
+        if max.z() - min.z() < BOUNDING_BOX_PADDING { BOUNDING_BOX_PADDING } else { 0.0 },
+    );
+
+    min -= padding;
+    max += padding;
+
+    return Aabb::new(min, max);
+}
+
+// Watertight ray/triangle intersection (Woop, Benthin and Wald, 2013). Rays
+// hitting a shared edge or vertex hit at least one of the adjacent triangles.
+// Returns the ray parameter and the barycentric weights of v0, v1 and v2.
+pub fn intersect_triangle(r: &Ray, v0: Vec3, v1: Vec3, v2: Vec3, t_min: f64, t_max: f64) -> Option<(f64, f64, f64, f64)> {
+    let dir = r.direction();
+
+    // Permute the axes so that the ray direction is largest along z.
+    let kz = if dir.x().abs() > dir.y().abs() && dir.x().abs() > dir.z().abs() {
+        0
+    } else if dir.y().abs() > dir.z().abs() {
+        1
+    } else {
+        2
+    };
+
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear the vertices so that the ray points along +z.
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let a = v0 - r.origin();
+    let b = v1 - r.origin();
+    let c = v2 - r.origin();
+
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+
+    if det == 0.0 {
+        return None;
+    }
+
+    let az = sz * a[kz];
+    let bz = sz * b[kz];
+    let cz = sz * c[kz];
+    let t = (u * az + v * bz + w * cz) / det;
+
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+
+    return Some((t, u / det, v / det, w / det));
+}
+
+pub fn triangle_area(v0: Vec3, v1: Vec3, v2: Vec3) -> f64 {
+    return 0.5 * cross(v1 - v0, v2 - v0).length();
+}
+
+// A point chosen uniformly by area on the triangle, with its geometric
+// normal.
+pub fn sample_triangle(v0: Vec3, v1: Vec3, v2: Vec3, sampler: &mut Sampler) -> SurfaceSample {
+    let s = sampler.double_unit().sqrt();
+    let b1 = sampler.double_unit() * s;
+    let b2 = 1.0 - s;
+
+    let position = (1.0 - b1 - b2) * v0 + b1 * v1 + b2 * v2;
+
+    return SurfaceSample::new(position, unit_vector(cross(v1 - v0, v2 - v0)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(triangle: &Triangle, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new_default();
+
+        if triangle.intersect(&Ray::new(origin, direction, 0.0), 0.0, f64::INFINITY, &mut rec) {
+            return Some(rec);
+        }
+
+        return None;
+    }
+
+    #[test]
+    fn barycentric_coordinates() {
+        let (v0, v1, v2) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 0.0), Vec3::new(0.0, 3.0, 0.0));
+        let triangle = Triangle::new(v0, v1, v2);
+        let down = Vec3::new(0.0, 0.0, -1.0);
+
+        let rec = hit(&triangle, Vec3::new(1.0, 1.0, 1.0), down).unwrap();
+        let (b1, b2) = rec.barycentric();
+        assert!((b1 - 1.0 / 3.0).abs() < 1e-12 && (b2 - 1.0 / 3.0).abs() < 1e-12);
+
+        let rec = hit(&triangle, v1 + Vec3::new(0.0, 0.0, 1.0), down).unwrap();
+        let (b1, b2) = rec.barycentric();
+        assert!((b1 - 1.0).abs() < 1e-12 && b2.abs() < 1e-12);
+        assert_eq!(rec.uv(), rec.barycentric());
+    }
+
+    // Rays through points on an edge shared by two triangles must hit at
+    // least one of them, or light leaks through the mesh.
+    #[test]
+    fn shared_edge_is_watertight() {
+        let a = Triangle::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.1, 0.3), Vec3::new(0.2, 1.0, -0.4));
+        let b = Triangle::new(Vec3::new(1.0, 0.1, 0.3), Vec3::new(1.3, 1.2, 0.0), Vec3::new(0.2, 1.0, -0.4));
+        let mut sampler = Sampler::new(1);
+
+        for _ in 0..10000 {
+            let s = sampler.double_unit();
+            let point = (1.0 - s) * Vec3::new(1.0, 0.1, 0.3) + s * Vec3::new(0.2, 1.0, -0.4);
+            let direction = Vec3::new(sampler.double_range(-1.0, 1.0), sampler.double_range(-1.0, 1.0), -1.0);
+            let origin = point - 3.0 * direction;
+
+            assert!(hit(&a, origin, direction).is_some() || hit(&b, origin, direction).is_some());
+        }
+    }
+}
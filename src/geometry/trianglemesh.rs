@@ -0,0 +1,294 @@
+use super::*;
+
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::math::*;
+use crate::random::Sampler;
+
+// A triangle of a TriangleMesh, referring to the mesh's vertex buffers by
+// index. Positions, normals and texture coordinates are indexed separately,
+// so that vertices can share a position but have different normals or UVs.
+pub struct MeshFace {
+    positions: [u32; 3],
+    normals: Option<[u32; 3]>,
+    uvs: Option<[u32; 3]>,
+    material_id: u32,
+}
+
+impl MeshFace {
+    pub fn new(positions: [u32; 3], normals: Option<[u32; 3]>, uvs: Option<[u32; 3]>, material_id: u32) -> MeshFace {
+        return MeshFace{ positions, normals, uvs, material_id };
+    }
+}
+
+// The faces of a mesh with one material id, and the cumulative distribution
+// of their areas, for picking a face with probability proportional to its
+// area.
+struct FaceDistribution {
+    faces: Vec<u32>,
+    cdf: Vec<f64>,
+    area: f64,
+}
+
+impl FaceDistribution {
+    fn new() -> FaceDistribution {
+        return FaceDistribution { faces: Vec::new(), cdf: Vec::new(), area: 0.0 };
+    }
+}
+
+// A triangle mesh with shared vertex buffers and its own bounding volume
+// hierarchy over the faces, so that it can be added to a World as a single
+// entity.
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<MeshFace>,
+    bvh: Bvh,
+    // Indexed by material id.
+    face_distributions: Vec<FaceDistribution>,
+}
+
+impl TriangleMesh {
+    // An error naming the first face that refers to a position, normal or
+    // texture coordinate the mesh doesn't have.
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, faces: Vec<MeshFace>) -> Result<TriangleMesh, String> {
+        for (i, face) in faces.iter().enumerate() {
+            check_indices(i, &face.positions, positions.len(), "position")?;
+
+            if let Some(indices) = &face.normals {
+                check_indices(i, indices, normals.len(), "normal")?;
+            }
+
+            if let Some(indices) = &face.uvs {
+                check_indices(i, indices, uvs.len(), "texture coordinate")?;
+            }
+        }
+
+        let boxes: Vec<Aabb> = faces.iter().map(|face| {
+            let [i0, i1, i2] = face.positions;
+            return triangle_bounding_box(positions[i0 as usize], positions[i1 as usize], positions[i2 as usize]);
+        }).collect();
+
+        let bvh = Bvh::new(&boxes, SplitMethod::Sah);
+
+        let mut face_distributions: Vec<FaceDistribution> = Vec::new();
+
+        for (i, face) in faces.iter().enumerate() {
+            let material_id = face.material_id as usize;
+
+            while face_distributions.len() <= material_id {
+                face_distributions.push(FaceDistribution::new());
+            }
+
+            let [i0, i1, i2] = face.positions;
+            let distribution = &mut face_distributions[material_id];
+            distribution.area += triangle_area(positions[i0 as usize], positions[i1 as usize], positions[i2 as usize]);
+            distribution.faces.push(i as u32);
+            distribution.cdf.push(distribution.area);
+        }
+
+        for distribution in &mut face_distributions {
+            for value in &mut distribution.cdf {
+                *value /= distribution.area;
+            }
+        }
+
+        return Ok(TriangleMesh{ positions, normals, uvs, faces, bvh, face_distributions });
+    }
+
+    fn vertices(&self, face: &MeshFace) -> (Vec3, Vec3, Vec3) {
+        let [i0, i1, i2] = face.positions;
+        return (self.positions[i0 as usize], self.positions[i1 as usize], self.positions[i2 as usize]);
+    }
+}
+
+fn check_indices(face: usize, indices: &[u32; 3], count: usize, kind: &str) -> Result<(), String> {
+    return match indices.iter().find(|&&index| index as usize >= count) {
+        Some(index) => Err(format!("face {} refers to {} {}, but the mesh has {}", face, kind, index, count)),
+        None => Ok(()),
+    };
+}
+
+impl Geometry for TriangleMesh {
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut closest_hit = None;
+
+        self.bvh.intersect(r, t_min, t_max, |i, t_min, t_max| {
+            let (v0, v1, v2) = self.vertices(&self.faces[i]);
+
+            return match intersect_triangle(r, v0, v1, v2, t_min, t_max) {
+                Some((t, b0, b1, b2)) => {
+                    closest_hit = Some((i, t, b0, b1, b2));
+                    Some(t)
+                },
+                None => None,
+            };
+        });
+
+        let (face_index, t, b0, b1, b2) = match closest_hit {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let face = &self.faces[face_index];
+        let (v0, v1, v2) = self.vertices(face);
+
+        let mut outward_normal = unit_vector(cross(v1 - v0, v2 - v0));
+        let shading_normal = face.normals.map(|[n0, n1, n2]| {
+            return unit_vector(b0 * self.normals[n0 as usize] + b1 * self.normals[n1 as usize] + b2 * self.normals[n2 as usize]);
+        });
+
+        // With vertex normals, the side the normals point to is the outside,
+        // regardless of the winding of the vertices.
+        if let Some(shading_normal) = shading_normal {
+            if dot(outward_normal, shading_normal) < 0.0 {
+                outward_normal = -outward_normal;
+            }
+        }
+
+        *rec = HitRecord::new(r.at(t), outward_normal, t, false);
+        rec.set_face_normal(r, outward_normal);
+        rec.set_barycentric(b1, b2);
+        rec.set_material_id(face.material_id as usize);
+
+        if let Some(shading_normal) = shading_normal {
+            rec.set_shading_normal(shading_normal);
+        }
+
+        match face.uvs {
+            Some([t0, t1, t2]) => {
+                let (u0, v0) = self.uvs[t0 as usize];
+                let (u1, v1) = self.uvs[t1 as usize];
+                let (u2, v2) = self.uvs[t2 as usize];
+                rec.set_uv(b0 * u0 + b1 * u1 + b2 * u2, b0 * v0 + b1 * v1 + b2 * v2);
+            },
+            None => {
+                let (u, v) = rec.barycentric();
+                rec.set_uv(u, v);
+            },
+        }
+
+        return true;
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
+        return match self.bvh.bounding_box() {
+            Some(bounding_box) => {
+                *output_box = bounding_box;
+                true
+            },
+            None => false,
+        };
+    }
+
+    fn area(&self, material_id: usize) -> f64 {
+        return match self.face_distributions.get(material_id) {
+            Some(distribution) => distribution.area,
+            None => 0.0,
+        };
+    }
+
+    fn sample_surface(&self, material_id: usize, _time: f64, sampler: &mut Sampler) -> Option<SurfaceSample> {
+        let distribution = self.face_distributions.get(material_id)?;
+
+        if distribution.area <= 0.0 {
+            return None;
+        }
+
+        let u = sampler.double_unit();
+        let index = distribution.cdf.partition_point(|&value| value <= u).min(distribution.faces.len() - 1);
+        let (v0, v1, v2) = self.vertices(&self.faces[distribution.faces[index] as usize]);
+
+        return Some(sample_triangle(v0, v1, v2, sampler));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit square in the z = 0 plane made of two triangles with material
+    // ids 0 and 1, and a triangle of material 1 twice as large at z = -1.
+    fn test_mesh() -> TriangleMesh {
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0), Vec3::new(2.0, 0.0, -1.0), Vec3::new(0.0, 2.0, -1.0),
+        ];
+
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+
+        let faces = vec![
+            MeshFace::new([0, 1, 2], None, Some([0, 1, 2]), 0),
+            MeshFace::new([0, 2, 3], None, Some([0, 2, 3]), 1),
+            MeshFace::new([4, 5, 6], None, None, 1),
+        ];
+
+        return TriangleMesh::new(positions, Vec::new(), uvs, faces).unwrap();
+    }
+
+    #[test]
+    fn intersection() {
+        let mesh = test_mesh();
+        let mut rec = HitRecord::new_default();
+        let down = Vec3::new(0.0, 0.0, -1.0);
+
+        assert!(mesh.intersect(&Ray::new(Vec3::new(0.75, 0.25, 1.0), down, 0.0), 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.material_id(), 0);
+        assert!((rec.t() - 1.0).abs() < 1e-12);
+        assert!((rec.uv().0 - 0.75).abs() < 1e-12 && (rec.uv().1 - 0.25).abs() < 1e-12);
+
+        assert!(mesh.intersect(&Ray::new(Vec3::new(0.25, 0.75, 1.0), down, 0.0), 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.material_id(), 1);
+
+        // Only the larger triangle behind the square.
+        assert!(mesh.intersect(&Ray::new(Vec3::new(1.5, 0.25, 1.0), down, 0.0), 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.material_id(), 1);
+        assert!((rec.t() - 2.0).abs() < 1e-12);
+
+        assert!(!mesh.intersect(&Ray::new(Vec3::new(1.5, 1.5, 1.0), down, 0.0), 0.001, f64::INFINITY, &mut rec));
+    }
+
+    #[test]
+    fn area_and_samples_by_material() {
+        let mesh = test_mesh();
+
+        assert!((mesh.area(0) - 0.5).abs() < 1e-12);
+        assert!((mesh.area(1) - 2.5).abs() < 1e-12);
+        assert_eq!(mesh.area(2), 0.0);
+        assert!(mesh.sample_surface(2, 0.0, &mut Sampler::new(1)).is_none());
+
+        // Material 1 is picked by area, so 4 in 5 samples are on the larger
+        // triangle.
+        let mut sampler = Sampler::new(1);
+        let mut behind = 0;
+
+        for _ in 0..10000 {
+            let sample = mesh.sample_surface(1, 0.0, &mut sampler).unwrap();
+            let p = sample.position();
+
+            if p.z() < -0.5 {
+                assert!(p.x() >= 0.0 && p.y() >= 0.0 && p.x() + p.y() <= 2.0 + 1e-12);
+                behind += 1;
+            } else {
+                assert!(p.x() >= 0.0 && p.x() <= p.y() + 1e-12 && p.y() <= 1.0);
+            }
+        }
+
+        assert!((behind as f64 / 10000.0 - 0.8).abs() < 0.02);
+    }
+
+    #[test]
+    fn bad_indices() {
+        let positions = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+
+        let error = |faces: Vec<MeshFace>| match TriangleMesh::new(positions.clone(), vec![Vec3::new(0.0, 0.0, 1.0)], Vec::new(), faces) {
+            Ok(_) => panic!("the mesh was built"),
+            Err(message) => message,
+        };
+
+        assert_eq!(error(vec![MeshFace::new([0, 1, 2], None, None, 0), MeshFace::new([0, 3, 2], None, None, 0)]), "face 1 refers to position 3, but the mesh has 3");
+        assert_eq!(error(vec![MeshFace::new([0, 1, 2], Some([0, 0, 1]), None, 0)]), "face 0 refers to normal 1, but the mesh has 1");
+        assert_eq!(error(vec![MeshFace::new([0, 1, 2], None, Some([0, 0, 0]), 0)]), "face 0 refers to texture coordinate 0, but the mesh has 0");
+    }
+}
@@ -0,0 +1,461 @@
+// A zlib (RFC 1950) stream compressor, using LZ77 matching and a dynamic
+// Huffman code (RFC 1951) for every block.
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+const TOKENS_PER_BLOCK: usize = 1 << 16;
+
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+const END_OF_BLOCK: usize = 256;
+
+pub const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+pub const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        return BitWriter { bytes: Vec::new(), buffer: 0, count: 0 };
+    }
+
+    // Writes the lowest count bits of value, least significant bit first.
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are stored most significant bit first.
+    fn write_code(&mut self, code: u16, length: u8) {
+        self.write_bits(reverse_bits(code, length) as u32, length as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        return self.bytes;
+    }
+}
+
+fn reverse_bits(code: u16, length: u8) -> u16 {
+    return code.reverse_bits() >> (16 - length as u32);
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    // 5552 is the largest number of bytes that can be summed before b
+    // overflows.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    return (b << 16) | a;
+}
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let tokens = find_matches(data);
+    let mut writer = BitWriter::new();
+
+    // CMF: deflate with a 32K window, FLG: default compression level, with
+    // the check bits making CMF * 256 + FLG a multiple of 31.
+    writer.write_bits(0x78, 8);
+    writer.write_bits(0x9c, 8);
+
+    let block_count = tokens.len().div_ceil(TOKENS_PER_BLOCK).max(1);
+
+    for block in 0..block_count {
+        let start = block * TOKENS_PER_BLOCK;
+        let end = (start + TOKENS_PER_BLOCK).min(tokens.len());
+        write_block(&mut writer, &tokens[start..end], block == block_count - 1);
+    }
+
+    let mut bytes = writer.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+
+    return bytes;
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let value = ((data[i] as u32) << 16) | ((data[i + 1] as u32) << 8) | (data[i + 2] as u32);
+    return (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+}
+
+// Adds position i to the hash chain of the three bytes starting there.
+fn insert_position(data: &[u8], i: usize, head: &mut [usize], previous: &mut [usize]) {
+    if i + MIN_MATCH <= data.len() {
+        let h = hash(data, i);
+        previous[i] = head[h];
+        head[h] = i;
+    }
+}
+
+fn find_matches(data: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut i = 0;
+
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if i + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(data, i)];
+            let mut chain = 0;
+
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let length = data[candidate..candidate + max_length].iter().zip(&data[i..i + max_length]).take_while(|(a, b)| a == b).count();
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            tokens.push(Token::Match { length: best_length as u16, distance: best_distance as u16 });
+
+            for j in i..i + best_length {
+                insert_position(data, j, &mut head, &mut previous);
+            }
+
+            i += best_length;
+        } else {
+            tokens.push(Token::Literal(data[i]));
+            insert_position(data, i, &mut head, &mut previous);
+            i += 1;
+        }
+    }
+
+    return tokens;
+}
+
+fn length_code(length: u16) -> usize {
+    return LENGTH_BASE.iter().rposition(|&base| base <= length).unwrap_or(0);
+}
+
+fn distance_code(distance: u16) -> usize {
+    return DISTANCE_BASE.iter().rposition(|&base| base <= distance).unwrap_or(0);
+}
+
+// Computes Huffman code lengths for the given symbol frequencies, with no
+// code longer than max_length. Unused symbols get length 0.
+fn code_lengths(frequencies: &[u32], max_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let mut symbols: Vec<usize> = (0..frequencies.len()).filter(|&s| frequencies[s] > 0).collect();
+
+    if symbols.is_empty() {
+        return lengths;
+    }
+
+    if symbols.len() == 1 {
+        lengths[symbols[0]] = 1;
+        return lengths;
+    }
+
+    // Build the tree by repeatedly merging the two least frequent nodes, and
+    // read the depth of every leaf from the parent links.
+    let leaf_count = symbols.len();
+    let mut weights: Vec<u64> = symbols.iter().map(|&s| frequencies[s] as u64).collect();
+    let mut parents = vec![usize::MAX; leaf_count];
+    let mut queue: std::collections::BinaryHeap<std::cmp::Reverse<(u64, usize)>> = (0..leaf_count).map(|n| std::cmp::Reverse((weights[n], n))).collect();
+
+    while queue.len() > 1 {
+        let std::cmp::Reverse((w1, n1)) = queue.pop().unwrap();
+        let std::cmp::Reverse((w2, n2)) = queue.pop().unwrap();
+        let node = weights.len();
+
+        weights.push(w1 + w2);
+        parents.push(usize::MAX);
+        parents[n1] = node;
+        parents[n2] = node;
+        queue.push(std::cmp::Reverse((w1 + w2, node)));
+    }
+
+    let mut length_counts = vec![0u32; leaf_count.max(max_length as usize) + 1];
+    let mut depths = vec![0usize; leaf_count];
+
+    for (leaf, depth) in depths.iter_mut().enumerate() {
+        let mut node = leaf;
+
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            *depth += 1;
+        }
+
+        length_counts[*depth] += 1;
+    }
+
+    // Push codes that are too long up the tree, keeping the code complete.
+    let max = max_length as usize;
+
+    for i in (max + 1..length_counts.len()).rev() {
+        while length_counts[i] > 0 {
+            let mut j = i - 2;
+
+            while length_counts[j] == 0 {
+                j -= 1;
+            }
+
+            length_counts[i] -= 2;
+            length_counts[i - 1] += 1;
+            length_counts[j + 1] += 2;
+            length_counts[j] -= 1;
+        }
+    }
+
+    // Hand out the lengths, shortest first, to the most frequent symbols.
+    symbols.sort_by(|&a, &b| frequencies[b].cmp(&frequencies[a]).then(a.cmp(&b)));
+    let mut next = symbols.iter();
+
+    for (length, &count) in length_counts.iter().enumerate().take(max + 1) {
+        for _ in 0..count {
+            if let Some(&symbol) = next.next() {
+                lengths[symbol] = length as u8;
+            }
+        }
+    }
+
+    return lengths;
+}
+
+// The canonical Huffman codes for a set of code lengths (RFC 1951, 3.2.2).
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut length_counts = [0u16; 16];
+
+    for &length in lengths {
+        length_counts[length as usize] += 1;
+    }
+
+    length_counts[0] = 0;
+
+    let mut next_code = [0u16; 16];
+    let mut code = 0u16;
+
+    for bits in 1..16 {
+        code = (code + length_counts[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+
+    let mut codes = vec![0u16; lengths.len()];
+
+    for (symbol, &length) in lengths.iter().enumerate() {
+        if length != 0 {
+            codes[symbol] = next_code[length as usize];
+            next_code[length as usize] += 1;
+        }
+    }
+
+    return codes;
+}
+
+// Run length encodes the concatenated literal/length and distance code
+// lengths as (symbol, extra bits value) pairs of the code length alphabet.
+fn encode_code_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+
+        if length == 0 && run >= 11 {
+            let n = run.min(138);
+            encoded.push((18, (n - 11) as u8));
+            i += n;
+        } else if length == 0 && run >= 3 {
+            encoded.push((17, (run - 3) as u8));
+            i += run;
+        } else if length != 0 && run >= 4 {
+            encoded.push((length, 0));
+            let n = (run - 1).min(6);
+            encoded.push((16, (n - 3) as u8));
+            i += 1 + n;
+        } else {
+            encoded.push((length, 0));
+            i += 1;
+        }
+    }
+
+    return encoded;
+}
+
+fn write_block(writer: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut literal_frequencies = vec![0u32; 286];
+    let mut distance_frequencies = vec![0u32; 30];
+    literal_frequencies[END_OF_BLOCK] = 1;
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_frequencies[257 + length_code(length)] += 1;
+                distance_frequencies[distance_code(distance)] += 1;
+            },
+        }
+    }
+
+    // At least one distance code has to be present, even if unused.
+    if distance_frequencies.iter().all(|&f| f == 0) {
+        distance_frequencies[0] = 1;
+    }
+
+    let literal_lengths = code_lengths(&literal_frequencies, MAX_CODE_LENGTH);
+    let distance_lengths = code_lengths(&distance_frequencies, MAX_CODE_LENGTH);
+    let literal_codes = canonical_codes(&literal_lengths);
+    let distance_codes = canonical_codes(&distance_lengths);
+
+    let literal_count = 257.max(literal_lengths.iter().rposition(|&l| l != 0).unwrap_or(0) + 1);
+    let distance_count = 1.max(distance_lengths.iter().rposition(|&l| l != 0).unwrap_or(0) + 1);
+
+    let mut all_lengths = literal_lengths[..literal_count].to_vec();
+    all_lengths.extend_from_slice(&distance_lengths[..distance_count]);
+    let encoded_lengths = encode_code_lengths(&all_lengths);
+
+    let mut code_length_frequencies = vec![0u32; 19];
+
+    for &(symbol, _) in &encoded_lengths {
+        code_length_frequencies[symbol as usize] += 1;
+    }
+
+    let code_length_lengths = code_lengths(&code_length_frequencies, MAX_CODE_LENGTH_CODE_LENGTH);
+    let code_length_codes = canonical_codes(&code_length_lengths);
+    let code_length_count = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&s| code_length_lengths[s] != 0).unwrap_or(0) + 1);
+
+    writer.write_bits(if last { 1 } else { 0 }, 1);
+    writer.write_bits(2, 2);
+    writer.write_bits((literal_count - 257) as u32, 5);
+    writer.write_bits((distance_count - 1) as u32, 5);
+    writer.write_bits((code_length_count - 4) as u32, 4);
+
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        writer.write_bits(code_length_lengths[symbol] as u32, 3);
+    }
+
+    for &(symbol, extra) in &encoded_lengths {
+        writer.write_code(code_length_codes[symbol as usize], code_length_lengths[symbol as usize]);
+
+        match symbol {
+            16 => writer.write_bits(extra as u32, 2),
+            17 => writer.write_bits(extra as u32, 3),
+            18 => writer.write_bits(extra as u32, 7),
+            _ => {},
+        }
+    }
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => writer.write_code(literal_codes[byte as usize], literal_lengths[byte as usize]),
+            Token::Match { length, distance } => {
+                let lc = length_code(length);
+                writer.write_code(literal_codes[257 + lc], literal_lengths[257 + lc]);
+                writer.write_bits((length - LENGTH_BASE[lc]) as u32, LENGTH_EXTRA_BITS[lc] as u32);
+
+                let dc = distance_code(distance);
+                writer.write_code(distance_codes[dc], distance_lengths[dc]);
+                writer.write_bits((distance - DISTANCE_BASE[dc]) as u32, DISTANCE_EXTRA_BITS[dc] as u32);
+            },
+        }
+    }
+
+    writer.write_code(literal_codes[END_OF_BLOCK], literal_lengths[END_OF_BLOCK]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::zlib_decompress;
+    use crate::random::Sampler;
+
+    fn random_bytes(count: usize, seed: u64) -> Vec<u8> {
+        let mut sampler = Sampler::new(seed);
+        return (0..count).map(|_| sampler.next_u64() as u8).collect();
+    }
+
+    fn assert_round_trip(data: &[u8]) {
+        assert_eq!(zlib_decompress(&zlib_compress(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn round_trip_small() {
+        assert_round_trip(&[]);
+        assert_round_trip(b"a");
+        assert_round_trip(b"hello hello hello hello");
+    }
+
+    #[test]
+    fn round_trip_random() {
+        // More tokens than fit in one block.
+        assert_round_trip(&random_bytes(200_000, 1));
+    }
+
+    #[test]
+    fn round_trip_repetitive() {
+        let zeros = vec![0u8; 1 << 20];
+        let compressed = zlib_compress(&zeros);
+
+        assert!(compressed.len() < zeros.len() / 500);
+        assert_eq!(zlib_decompress(&compressed).unwrap(), zeros);
+
+        let text: Vec<u8> = b"the quick brown fox jumps over the lazy dog ".iter().cycle().take(100_000).cloned().collect();
+        assert_round_trip(&text);
+    }
+
+    #[test]
+    fn round_trip_repeats_beyond_the_window() {
+        let block = random_bytes(WINDOW_SIZE + 1000, 2);
+        let mut data = block.clone();
+        data.extend_from_slice(&block);
+        data.extend_from_slice(&block[..500]);
+
+        assert_round_trip(&data);
+    }
+}
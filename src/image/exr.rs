@@ -0,0 +1,550 @@
+use super::*;
+
+use crate::math::*;
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::Write;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExrCompression {
+    None,
+    Zip,
+}
+
+impl ExrCompression {
+    fn code(&self) -> u8 {
+        return match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        };
+    }
+
+    fn lines_per_block(&self) -> usize {
+        return match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        };
+    }
+}
+
+// Pixel type of a channel in the file. Only 32 bit floats are written.
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+// Deflate can't shrink data by more than this factor, and run length
+// encoding by less, which bounds the size of an image that fits in a file.
+const MAX_COMPRESSION_RATIO: usize = 1032;
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn box2i(x_max: i32, y_max: i32) -> Vec<u8> {
+    let mut value = Vec::new();
+
+    for v in &[0, 0, x_max, y_max] {
+        value.extend_from_slice(&v.to_le_bytes());
+    }
+
+    return value;
+}
+
+fn header(image: &Image, compression: ExrCompression) -> Vec<u8> {
+    let mut header = Vec::new();
+
+    // Magic number and version 2, single part scanline file.
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    // Channels have to be listed in alphabetical order.
+    let mut channels = Vec::new();
+
+    for name in &["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+
+    channels.push(0);
+
+    let window = box2i(image.width() as i32 - 1, image.height() as i32 - 1);
+
+    write_attribute(&mut header, "channels", "chlist", &channels);
+    write_attribute(&mut header, "compression", "compression", &[compression.code()]);
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    return header;
+}
+
+// Each scanline stores all values of one channel before the next channel.
+fn block_data(image: &Image, y_start: usize, y_end: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity((y_end - y_start) * image.width() * 12);
+
+    for y in y_start..y_end {
+        let row = image.row(y);
+
+        for channel in &[2, 1, 0] {
+            for pixel in row {
+                let value = match channel {
+                    0 => pixel.r(),
+                    1 => pixel.g(),
+                    _ => pixel.b(),
+                };
+
+                data.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+    }
+
+    return data;
+}
+
+// Splits the bytes into two halves of even and odd positions and replaces
+// them by their differences, which makes float data compress much better.
+fn zip_predictor(data: &[u8]) -> Vec<u8> {
+    let half = data.len().div_ceil(2);
+    let mut interleaved = vec![0u8; data.len()];
+
+    for (i, &byte) in data.iter().enumerate() {
+        let index = if i % 2 == 0 { i / 2 } else { half + i / 2 };
+        interleaved[index] = byte;
+    }
+
+    for i in (1..interleaved.len()).rev() {
+        interleaved[i] = interleaved[i].wrapping_sub(interleaved[i - 1]).wrapping_add(128);
+    }
+
+    return interleaved;
+}
+
+// Writes a scanline OpenEXR file with linear 32 bit float RGB channels.
+pub fn write_exr(out: &mut dyn Write, image: &Image, compression: ExrCompression) -> io::Result<()> {
+    let header = header(image, compression);
+    let lines_per_block = compression.lines_per_block();
+    let block_count = image.height().div_ceil(lines_per_block);
+
+    let mut blocks = Vec::with_capacity(block_count);
+
+    for block in 0..block_count {
+        let y_start = block * lines_per_block;
+        let y_end = (y_start + lines_per_block).min(image.height());
+        let data = block_data(image, y_start, y_end);
+
+        let data = match compression {
+            ExrCompression::None => data,
+            ExrCompression::Zip => {
+                let compressed = zlib_compress(&zip_predictor(&data));
+
+                // Readers treat blocks that did not shrink as uncompressed.
+                if compressed.len() < data.len() { compressed } else { data }
+            },
+        };
+
+        blocks.push((y_start, data));
+    }
+
+    out.write_all(&header)?;
+
+    let mut offset = (header.len() + 8 * block_count) as u64;
+
+    for (_, data) in &blocks {
+        out.write_all(&offset.to_le_bytes())?;
+        offset += 8 + data.len() as u64;
+    }
+
+    for (y, data) in &blocks {
+        out.write_all(&(*y as i32).to_le_bytes())?;
+        out.write_all(&(data.len() as i32).to_le_bytes())?;
+        out.write_all(data)?;
+    }
+
+    return out.flush();
+}
+
+// Converts an IEEE 754 half precision float.
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+
+    return match exponent {
+        0 => sign * mantissa * 2f64.powi(-24),
+        31 => if mantissa == 0.0 { sign * f64::INFINITY } else { f64::NAN },
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    };
+}
+
+// Reverses zip_predictor.
+fn undo_predictor(data: &mut [u8]) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i].wrapping_add(data[i - 1]).wrapping_sub(128);
+    }
+
+    let half = data.len().div_ceil(2);
+    let mut output = vec![0u8; data.len()];
+
+    for (i, byte) in output.iter_mut().enumerate() {
+        *byte = if i % 2 == 0 { data[i / 2] } else { data[half + i / 2] };
+    }
+
+    return output;
+}
+
+fn rle_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let count = data[i] as i8;
+        i += 1;
+
+        if count < 0 {
+            let count = (-(count as i32)) as usize;
+
+            if i + count > data.len() {
+                return Err(String::from("invalid run length encoded block"));
+            }
+
+            output.extend_from_slice(&data[i..i + count]);
+            i += count;
+        } else {
+            if i >= data.len() {
+                return Err(String::from("invalid run length encoded block"));
+            }
+
+            output.extend(std::iter::repeat_n(data[i], count as usize + 1));
+            i += 1;
+        }
+    }
+
+    return Ok(output);
+}
+
+struct Channel {
+    name: String,
+    pixel_type: i32,
+}
+
+impl Channel {
+    fn sample_size(&self) -> usize {
+        return if self.pixel_type == 1 { 2 } else { 4 };
+    }
+}
+
+fn read_i32(data: &[u8], position: usize) -> Result<i32, String> {
+    if position.checked_add(4).is_none_or(|end| end > data.len()) {
+        return Err(String::from("unexpected end of file"));
+    }
+
+    return Ok(i32::from_le_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]]));
+}
+
+fn read_string<'a>(data: &'a [u8], position: &mut usize) -> Result<&'a str, String> {
+    let start = *position;
+
+    while *position < data.len() && data[*position] != 0 {
+        *position += 1;
+    }
+
+    if *position >= data.len() {
+        return Err(String::from("unexpected end of the header"));
+    }
+
+    *position += 1;
+
+    return std::str::from_utf8(&data[start..*position - 1]).map_err(|_| String::from("invalid header"));
+}
+
+fn parse_channels(value: &[u8]) -> Result<Vec<Channel>, String> {
+    let mut channels = Vec::new();
+    let mut position = 0;
+
+    while position < value.len() && value[position] != 0 {
+        let name = read_string(value, &mut position)?.to_string();
+        let pixel_type = read_i32(value, position)?;
+        let x_sampling = read_i32(value, position + 8)?;
+        let y_sampling = read_i32(value, position + 12)?;
+        position += 16;
+
+        if !(0..=2).contains(&pixel_type) {
+            return Err(format!("channel '{}' has unknown pixel type {}", name, pixel_type));
+        }
+
+        if x_sampling != 1 || y_sampling != 1 {
+            return Err(format!("channel '{}' is subsampled, which is not supported", name));
+        }
+
+        channels.push(Channel { name, pixel_type });
+    }
+
+    return Ok(channels);
+}
+
+// Reads a single part scanline OpenEXR file that is uncompressed or uses
+// RLE, ZIPS or ZIP compression. The R, G and B channels are used, or Y for
+// greyscale images.
+pub fn read_exr(data: &[u8]) -> Result<Image, String> {
+    if data.len() < 8 || data[0..4] != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(String::from("not an OpenEXR file"));
+    }
+
+    let flags = read_i32(data, 4)? >> 8;
+
+    if flags & 0x1a != 0 {
+        return Err(String::from("only single part scanline files are supported"));
+    }
+
+    let mut position = 8;
+    let mut channels = None;
+    let mut compression = None;
+    let mut data_window = None;
+
+    loop {
+        let name = read_string(data, &mut position)?;
+
+        if name.is_empty() {
+            break;
+        }
+
+        let _attribute_type = read_string(data, &mut position)?;
+        let size = usize::try_from(read_i32(data, position)?).map_err(|_| format!("invalid size of attribute '{}'", name))?;
+
+        if data.len() - (position + 4) < size {
+            return Err(format!("invalid size of attribute '{}'", name));
+        }
+
+        let value = &data[position + 4..position + 4 + size];
+        position += 4 + size;
+
+        match name {
+            "channels" => channels = Some(parse_channels(value)?),
+            "compression" if !value.is_empty() => compression = Some(value[0]),
+            "dataWindow" => data_window = Some((read_i32(value, 0)?, read_i32(value, 4)?, read_i32(value, 8)?, read_i32(value, 12)?)),
+            _ => {},
+        }
+    }
+
+    let channels = channels.ok_or_else(|| String::from("missing channels attribute"))?;
+    let (x_min, y_min, x_max, y_max) = data_window.ok_or_else(|| String::from("missing dataWindow attribute"))?;
+
+    let lines_per_block = match compression {
+        Some(0) | Some(1) | Some(2) => 1,
+        Some(3) => 16,
+        Some(c) => return Err(format!("unsupported compression method {}", c)),
+        None => return Err(String::from("missing compression attribute")),
+    };
+
+    if x_max < x_min || y_max < y_min {
+        return Err(String::from("image has no pixels"));
+    }
+
+    // In 64 bits, since the window can span the whole range of i32.
+    let width = (x_max as i64 - x_min as i64 + 1) as usize;
+    let height = (y_max as i64 - y_min as i64 + 1) as usize;
+
+    let find = |name: &str| channels.iter().position(|channel| channel.name == name);
+
+    let sources = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err(String::from("no R, G and B or Y channels")),
+    };
+
+    // Byte offset of each channel within a scanline.
+    let mut channel_offsets = Vec::with_capacity(channels.len());
+    let mut line_size = 0;
+
+    for channel in &channels {
+        channel_offsets.push(line_size);
+        line_size = channel.sample_size().checked_mul(width).and_then(|size| size.checked_add(line_size)).ok_or_else(|| String::from("image is too large"))?;
+    }
+
+    let block_count = height.div_ceil(lines_per_block);
+
+    // Checked before allocating, so that a header alone can't ask for an
+    // image of any size.
+    if (data.len() - position) / 8 < block_count {
+        return Err(String::from("unexpected end of the offset table"));
+    }
+
+    if line_size.checked_mul(height).is_none_or(|size| size / MAX_COMPRESSION_RATIO > data.len()) {
+        return Err(format!("not enough pixel data for a {}x{} image", width, height));
+    }
+
+    let mut image = Image::new(width, height);
+
+    for block in 0..block_count {
+        let offset_position = position + 8 * block;
+        let mut offset_bytes = [0u8; 8];
+        offset_bytes.copy_from_slice(&data[offset_position..offset_position + 8]);
+
+        let offset = match usize::try_from(u64::from_le_bytes(offset_bytes)) {
+            Ok(offset) if offset <= data.len() - 8 => offset,
+            _ => return Err(format!("invalid offset of block {}", block)),
+        };
+
+        let y_start = read_i32(data, offset)? as i64 - y_min as i64;
+        let size = usize::try_from(read_i32(data, offset + 4)?).map_err(|_| format!("invalid size of block {}", block))?;
+
+        if y_start < 0 || y_start as usize >= height || data.len() - (offset + 8) < size {
+            return Err(format!("invalid block {}", block));
+        }
+
+        let y_start = y_start as usize;
+        let lines = lines_per_block.min(height - y_start);
+        let expected_size = lines * line_size;
+        let compressed = &data[offset + 8..offset + 8 + size];
+
+        // Blocks that would not shrink are stored uncompressed.
+        let pixels = if compressed.len() == expected_size || compression == Some(0) {
+            compressed.to_vec()
+        } else if compression == Some(1) {
+            undo_predictor(&mut rle_decompress(compressed)?)
+        } else {
+            undo_predictor(&mut zlib_decompress(compressed)?)
+        };
+
+        if pixels.len() != expected_size {
+            return Err(format!("block {} has {} bytes, expected {}", block, pixels.len(), expected_size));
+        }
+
+        for line in 0..lines {
+            let line_data = &pixels[line * line_size..(line + 1) * line_size];
+
+            for (x, pixel) in image.row_mut(y_start + line).iter_mut().enumerate() {
+                let mut values = [0.0; 3];
+
+                for (value, &source) in values.iter_mut().zip(sources.iter()) {
+                    let channel = &channels[source];
+                    let start = channel_offsets[source] + x * channel.sample_size();
+                    let bytes = &line_data[start..start + channel.sample_size()];
+
+                    *value = match channel.pixel_type {
+                        0 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                        1 => half_to_f64(u16::from_le_bytes([bytes[0], bytes[1]])),
+                        _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    };
+                }
+
+                *pixel = Color::new(values[0], values[1], values[2]);
+            }
+        }
+    }
+
+    return Ok(image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryInto;
+
+    fn test_image() -> Image {
+        let mut image = Image::new(7, 20);
+
+        for y in 0..20 {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                *pixel = Color::new(x as f64 * 0.5, if x < 3 { 1.0 } else { y as f64 }, 1.0 / (1.0 + (x * y) as f64));
+            }
+        }
+
+        return image;
+    }
+
+    fn encode(image: &Image, compression: ExrCompression) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_exr(&mut data, image, compression).unwrap();
+        return data;
+    }
+
+    fn assert_round_trip(compression: ExrCompression) {
+        let image = test_image();
+        let read = read_exr(&encode(&image, compression)).unwrap();
+
+        assert_eq!((read.width(), read.height()), (image.width(), image.height()));
+
+        for (a, b) in image.pixels().iter().zip(read.pixels()) {
+            assert_eq!((a.r() as f32, a.g() as f32, a.b() as f32), (b.r() as f32, b.g() as f32, b.b() as f32));
+        }
+    }
+
+    // The position right after the header, where the offset table starts.
+    fn offset_table_position(data: &[u8]) -> usize {
+        let mut position = 8;
+
+        while !read_string(data, &mut position).unwrap().is_empty() {
+            read_string(data, &mut position).unwrap();
+            position += 4 + read_i32(data, position).unwrap() as usize;
+        }
+
+        return position;
+    }
+
+    fn replace(data: &mut [u8], position: usize, bytes: &[u8]) {
+        data[position..position + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn round_trip_uncompressed() {
+        assert_round_trip(ExrCompression::None);
+    }
+
+    #[test]
+    fn round_trip_zip() {
+        assert_round_trip(ExrCompression::Zip);
+    }
+
+    #[test]
+    fn truncated_file() {
+        let data = encode(&test_image(), ExrCompression::Zip);
+
+        assert!(read_exr(&data[..offset_table_position(&data) + 4]).is_err());
+        assert!(read_exr(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn huge_data_window() {
+        let mut data = encode(&test_image(), ExrCompression::None);
+        let window = data.windows(17).position(|bytes| bytes == b"dataWindow\0box2i\0").unwrap() + 17 + 4;
+
+        replace(&mut data, window, &i32::MIN.to_le_bytes());
+        replace(&mut data, window + 8, &i32::MAX.to_le_bytes());
+
+        assert!(read_exr(&data).is_err());
+    }
+
+    #[test]
+    fn invalid_block_offsets() {
+        let data = encode(&test_image(), ExrCompression::None);
+        let table = offset_table_position(&data);
+
+        for offset in [u64::MAX, u64::MAX - 4, data.len() as u64 - 4] {
+            let mut data = data.clone();
+            replace(&mut data, table, &offset.to_le_bytes());
+
+            assert!(read_exr(&data).is_err());
+        }
+
+        let mut data = data.clone();
+        let first_block = u64::from_le_bytes(data[table..table + 8].try_into().unwrap()) as usize;
+        replace(&mut data, first_block, &i32::MIN.to_le_bytes());
+        replace(&mut data, first_block + 4, &i32::MAX.to_le_bytes());
+
+        assert!(read_exr(&data).is_err());
+    }
+}
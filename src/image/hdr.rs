@@ -0,0 +1,410 @@
+use super::*;
+
+use crate::math::*;
+
+use std::io;
+use std::io::Write;
+
+// The largest value the shared exponent can represent, just below 2^127.
+const RGBE_MAX: f64 = 1.7e38;
+
+// Old style runs can be chained to repeat a pixel many times over. Longer
+// chains than this are rejected, so that every byte of pixel data decodes to
+// at most MAX_PIXELS_PER_BYTE pixels: a pixel followed by two run markers
+// covers 65536 pixels in 12 bytes.
+const MAX_RUN_SHIFT: usize = 8;
+const MAX_PIXELS_PER_BYTE: usize = 65536 / 12 + 1;
+
+// Shared exponent encoding of a color: three 8 bit mantissas and the
+// exponent of the largest component. Values too large to represent, and
+// infinities, are clamped, and NaNs become zero.
+fn to_rgbe(color: Color) -> [u8; 4] {
+    let clamp = |x: f64| if x > 0.0 { x.min(RGBE_MAX) } else { 0.0 };
+    let r = clamp(color.r());
+    let g = clamp(color.g());
+    let b = clamp(color.b());
+    let v = r.max(g).max(b);
+
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // v = m * 2^e with m in [0.5, 1).
+    let mut e = v.log2().floor() as i32 + 1;
+    let mut m = v / 2f64.powi(e);
+
+    if m >= 1.0 {
+        m /= 2.0;
+        e += 1;
+    } else if m < 0.5 {
+        m *= 2.0;
+        e -= 1;
+    }
+
+    let scale = m * 256.0 / v;
+
+    return [(r * scale) as u8, (g * scale) as u8, (b * scale) as u8, (e + 128) as u8];
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new_default();
+    }
+
+    let f = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+
+    return Color::new(
+        (rgbe[0] as f64 + 0.5) * f,
+        (rgbe[1] as f64 + 0.5) * f,
+        (rgbe[2] as f64 + 0.5) * f,
+    );
+}
+
+// Run length encodes one component of a scanline. Runs shorter than four
+// bytes are cheaper to store as part of a literal.
+fn encode_component(values: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+
+    while i < values.len() {
+        let mut run = 1;
+
+        while i + run < values.len() && run < 127 && values[i + run] == values[i] {
+            run += 1;
+        }
+
+        if run >= 4 {
+            output.push(128 + run as u8);
+            output.push(values[i]);
+            i += run;
+            continue;
+        }
+
+        // Extend the literal up to the start of the next long run.
+        let start = i;
+
+        while i < values.len() && i - start < 128 {
+            if i + 3 < values.len() && values[i] == values[i + 1] && values[i] == values[i + 2] && values[i] == values[i + 3] {
+                break;
+            }
+
+            i += 1;
+        }
+
+        output.push((i - start) as u8);
+        output.extend_from_slice(&values[start..i]);
+    }
+}
+
+// Writes a Radiance RGBE file with run length encoded scanlines.
+pub fn write_hdr(out: &mut dyn Write, image: &Image) -> io::Result<()> {
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height(), image.width())?;
+
+    let width = image.width();
+    let run_length_encoded = (8..0x8000).contains(&width);
+    let mut components = vec![0u8; width * 4];
+    let mut scanline = Vec::new();
+
+    for y in 0..image.height() {
+        scanline.clear();
+
+        if run_length_encoded {
+            for (x, &pixel) in image.row(y).iter().enumerate() {
+                let rgbe = to_rgbe(pixel);
+
+                for (c, &value) in rgbe.iter().enumerate() {
+                    components[c * width + x] = value;
+                }
+            }
+
+            scanline.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+
+            for component in components.chunks_exact(width) {
+                encode_component(component, &mut scanline);
+            }
+        } else {
+            for &pixel in image.row(y) {
+                scanline.extend_from_slice(&to_rgbe(pixel));
+            }
+        }
+
+        out.write_all(&scanline)?;
+    }
+
+    return out.flush();
+}
+
+fn read_line<'a>(data: &'a [u8], position: &mut usize) -> Result<&'a str, String> {
+    let start = *position;
+
+    while *position < data.len() && data[*position] != b'\n' {
+        *position += 1;
+    }
+
+    if *position == data.len() {
+        return Err(String::from("unexpected end of the header"));
+    }
+
+    *position += 1;
+
+    return std::str::from_utf8(&data[start..*position - 1]).map_err(|_| String::from("invalid header"));
+}
+
+fn next_byte(data: &[u8], position: &mut usize) -> Result<u8, String> {
+    if *position >= data.len() {
+        return Err(String::from("unexpected end of the pixel data"));
+    }
+
+    *position += 1;
+
+    return Ok(data[*position - 1]);
+}
+
+// Reads a scanline in the old formats, either flat or with runs marked by
+// pixels of (1, 1, 1, count) that repeat the previous pixel.
+fn read_flat_scanline(data: &[u8], position: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let mut x = 0;
+    let mut shift = 0;
+
+    while x < scanline.len() {
+        let mut rgbe = [0u8; 4];
+
+        for value in rgbe.iter_mut() {
+            *value = next_byte(data, position)?;
+        }
+
+        if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 {
+            if x == 0 {
+                return Err(String::from("run at the start of a scanline"));
+            }
+
+            if shift > MAX_RUN_SHIFT {
+                return Err(String::from("run too long"));
+            }
+
+            let count = (rgbe[3] as usize) << shift;
+
+            if x + count > scanline.len() {
+                return Err(String::from("run past the end of a scanline"));
+            }
+
+            for i in 0..count {
+                scanline[x + i] = scanline[x - 1];
+            }
+
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = rgbe;
+            x += 1;
+            shift = 0;
+        }
+    }
+
+    return Ok(());
+}
+
+fn read_rle_scanline(data: &[u8], position: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let width = scanline.len();
+
+    for c in 0..4 {
+        let mut x = 0;
+
+        while x < width {
+            let count = next_byte(data, position)? as usize;
+
+            if count > 128 {
+                let count = count - 128;
+                let value = next_byte(data, position)?;
+
+                if x + count > width {
+                    return Err(String::from("run past the end of a scanline"));
+                }
+
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[c] = value;
+                }
+
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(String::from("invalid literal in a scanline"));
+                }
+
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[c] = next_byte(data, position)?;
+                }
+
+                x += count;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+// Reads a Radiance RGBE file. Only the standard orientation, with rows from
+// top to bottom and columns from left to right, is supported.
+pub fn read_hdr(data: &[u8]) -> Result<Image, String> {
+    let mut position = 0;
+
+    if !read_line(data, &mut position)?.starts_with("#?") {
+        return Err(String::from("not a Radiance HDR file"));
+    }
+
+    loop {
+        let line = read_line(data, &mut position)?;
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format '{}'", format));
+            }
+        }
+    }
+
+    let resolution = read_line(data, &mut position)?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+
+    if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+        return Err(format!("unsupported resolution line '{}'", resolution));
+    }
+
+    let height: usize = fields[1].parse().map_err(|_| String::from("invalid height"))?;
+    let width: usize = fields[3].parse().map_err(|_| String::from("invalid width"))?;
+
+    if width == 0 || height == 0 {
+        return Err(String::from("image has no pixels"));
+    }
+
+    // Checked before allocating, so that a header alone can't ask for an
+    // image of any size.
+    let pixel_count = width.checked_mul(height).ok_or_else(|| String::from("image is too large"))?;
+
+    if pixel_count / MAX_PIXELS_PER_BYTE > data.len() - position {
+        return Err(format!("not enough pixel data for a {}x{} image", width, height));
+    }
+
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width];
+
+    for y in 0..height {
+        let new_format = (8..0x8000).contains(&width)
+            && position + 4 <= data.len()
+            && data[position] == 2
+            && data[position + 1] == 2
+            && data[position + 2] & 0x80 == 0;
+
+        if new_format {
+            let encoded_width = ((data[position + 2] as usize) << 8) | data[position + 3] as usize;
+
+            if encoded_width != width {
+                return Err(format!("scanline {} has width {}, expected {}", y, encoded_width, width));
+            }
+
+            position += 4;
+            read_rle_scanline(data, &mut position, &mut scanline)?;
+        } else {
+            read_flat_scanline(data, &mut position, &mut scanline)?;
+        }
+
+        for (pixel, &rgbe) in image.row_mut(y).iter_mut().zip(scanline.iter()) {
+            *pixel = from_rgbe(rgbe);
+        }
+    }
+
+    return Ok(image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+
+        for y in 0..height {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                // Runs of equal pixels as well as varying ones, to exercise
+                // both kinds of run length encoded packets.
+                let level = if x < width / 2 { 0.5 } else { x as f64 * 0.3 };
+                *pixel = Color::new(level, level * (y + 1) as f64, 100.0 / (1.0 + x as f64));
+            }
+        }
+
+        return image;
+    }
+
+    // RGBE stores 8 bits of mantissa for the largest component, so the
+    // others are only accurate relative to it.
+    fn assert_close(a: &Image, b: &Image) {
+        assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+
+        for (a, b) in a.pixels().iter().zip(b.pixels()) {
+            let tolerance = a.r().max(a.g()).max(a.b()) / 128.0;
+
+            assert!((a.r() - b.r()).abs() <= tolerance && (a.g() - b.g()).abs() <= tolerance && (a.b() - b.b()).abs() <= tolerance);
+        }
+    }
+
+    #[test]
+    fn round_trip_run_length_encoded() {
+        let image = test_image(40, 4);
+        let mut data = Vec::new();
+        write_hdr(&mut data, &image).unwrap();
+
+        assert_close(&image, &read_hdr(&data).unwrap());
+    }
+
+    #[test]
+    fn round_trip_flat() {
+        let image = test_image(5, 3);
+        let mut data = Vec::new();
+        write_hdr(&mut data, &image).unwrap();
+
+        assert_close(&image, &read_hdr(&data).unwrap());
+    }
+
+    #[test]
+    fn non_finite_pixels() {
+        let mut image = Image::new(2, 1);
+        image.row_mut(0)[0] = Color::new(f64::INFINITY, 1.0, 0.0);
+        image.row_mut(0)[1] = Color::new(f64::NAN, 1e300, -1.0);
+
+        let mut data = Vec::new();
+        write_hdr(&mut data, &image).unwrap();
+        let read = read_hdr(&data).unwrap();
+
+        assert!(read.pixels().iter().all(|pixel| pixel.r().is_finite() && pixel.g().is_finite() && pixel.b().is_finite()));
+        assert!(read.row(0)[1].r() < read.row(0)[1].g() / 100.0);
+    }
+
+    #[test]
+    fn chained_runs() {
+        let mut data = b"#?RADIANCE\n\n-Y 1 +X 100000\n".to_vec();
+        data.extend_from_slice(&[10, 20, 30, 128]);
+
+        for _ in 0..8 {
+            data.extend_from_slice(&[1, 1, 1, 255]);
+        }
+
+        assert!(read_hdr(&data).is_err());
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        assert!(read_hdr(b"#?RADIANCE\n\n-Y 1000000 +X 1000000\n").is_err());
+        assert!(read_hdr(b"#?RADIANCE\n\n-Y 18446744073709551615 +X 18446744073709551615\n").is_err());
+    }
+
+    #[test]
+    fn truncated_pixel_data() {
+        let mut data = Vec::new();
+        write_hdr(&mut data, &test_image(40, 4)).unwrap();
+        data.truncate(data.len() - 3);
+
+        assert!(read_hdr(&data).is_err());
+    }
+}
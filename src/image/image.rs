@@ -0,0 +1,53 @@
+use crate::math::*;
+
+// A framebuffer of linear colors, stored row by row from the top left corner.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        return Image {
+            width,
+            height,
+            pixels: vec![Color::new_default(); width * height],
+        };
+    }
+
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        return &self.pixels;
+    }
+
+    pub fn row(&self, y: usize) -> &[Color] {
+        return &self.pixels[y * self.width..(y + 1) * self.width];
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [Color] {
+        return &mut self.pixels[y * self.width..(y + 1) * self.width];
+    }
+
+    // Root mean square difference over all channels of two images of the
+    // same size.
+    pub fn root_mean_square_error(&self, other: &Image) -> f64 {
+        let mut sum = 0.0;
+
+        for (a, b) in self.pixels.iter().zip(other.pixels.iter()) {
+            let dr = a.r() - b.r();
+            let dg = a.g() - b.g();
+            let db = a.b() - b.b();
+            sum += dr * dr + dg * dg + db * db;
+        }
+
+        return (sum / (3 * self.pixels.len()) as f64).sqrt();
+    }
+}
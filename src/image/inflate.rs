@@ -0,0 +1,339 @@
+use super::*;
+
+// A zlib (RFC 1950) stream decompressor for stored, fixed and dynamic
+// Huffman blocks (RFC 1951).
+
+const END_OF_BLOCK_SYMBOL: usize = 256;
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        return BitReader { data, position: 0, buffer: 0, count: 0 };
+    }
+
+    // Reads count bits, least significant bit first.
+    fn read_bits(&mut self, count: u32) -> Result<u32, String> {
+        while self.count < count {
+            if self.position >= self.data.len() {
+                return Err(String::from("unexpected end of the compressed data"));
+            }
+
+            self.buffer |= (self.data[self.position] as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+
+        let value = (self.buffer & ((1u64 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+
+        return Ok(value);
+    }
+
+    fn align_to_byte(&mut self) {
+        let skip = self.count % 8;
+        self.buffer >>= skip;
+        self.count -= skip;
+    }
+}
+
+// A canonical Huffman code given by the number of codes of each length and
+// the symbols sorted by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, String> {
+        let mut counts = [0u16; 16];
+
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        counts[0] = 0;
+
+        // Reject over-subscribed codes, which can't be decoded.
+        let mut left = 1i32;
+
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+
+            if left < 0 {
+                return Err(String::from("invalid Huffman code lengths"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        return Ok(Huffman { counts, symbols });
+    }
+
+    // Decodes one symbol, reading the code one bit at a time. Codes are
+    // stored most significant bit first.
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, String> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for length in 1..16 {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[length] as i32;
+
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        return Err(String::from("invalid Huffman code"));
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+
+    // Neither code can fail, both are complete.
+    let literal_length = Huffman::new(&lengths).unwrap();
+    let distance = Huffman::new(&[5u8; 30]).unwrap();
+
+    return (literal_length, distance);
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_length_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    if literal_length_count > 286 || distance_count > 30 {
+        return Err(String::from("too many codes in a dynamic block"));
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[symbol] = reader.read_bits(3)? as u8;
+    }
+
+    let code_length_code = Huffman::new(&code_length_lengths)?;
+    let mut lengths = Vec::with_capacity(literal_length_count + distance_count);
+
+    while lengths.len() < literal_length_count + distance_count {
+        let symbol = code_length_code.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + reader.read_bits(2)? as usize),
+                None => return Err(String::from("repeated code length without a previous length")),
+            },
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+
+        if lengths.len() + repeat > literal_length_count + distance_count {
+            return Err(String::from("too many code lengths in a dynamic block"));
+        }
+
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+
+    if lengths[END_OF_BLOCK_SYMBOL] == 0 {
+        return Err(String::from("dynamic block without an end of block code"));
+    }
+
+    let literal_length = Huffman::new(&lengths[..literal_length_count])?;
+    let distance = Huffman::new(&lengths[literal_length_count..])?;
+
+    return Ok((literal_length, distance));
+}
+
+fn inflate_block(reader: &mut BitReader, literal_length: &Huffman, distance: &Huffman, output: &mut Vec<u8>) -> Result<(), String> {
+    loop {
+        let symbol = literal_length.decode(reader)?;
+
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+
+        if symbol == END_OF_BLOCK_SYMBOL {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+
+        if index >= LENGTH_BASE.len() {
+            return Err(String::from("invalid length code"));
+        }
+
+        let length = LENGTH_BASE[index] as usize + reader.read_bits(LENGTH_EXTRA_BITS[index] as u32)? as usize;
+
+        let index = distance.decode(reader)?;
+
+        if index >= DISTANCE_BASE.len() {
+            return Err(String::from("invalid distance code"));
+        }
+
+        let offset = DISTANCE_BASE[index] as usize + reader.read_bits(DISTANCE_EXTRA_BITS[index] as u32)? as usize;
+
+        if offset > output.len() {
+            return Err(String::from("distance too far back"));
+        }
+
+        // The match may overlap the bytes it produces.
+        let start = output.len() - offset;
+
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6 {
+        return Err(String::from("zlib stream too short"));
+    }
+
+    let header = ((data[0] as u16) << 8) | data[1] as u16;
+
+    if data[0] & 0x0f != 8 || !header.is_multiple_of(31) {
+        return Err(String::from("invalid zlib header"));
+    }
+
+    if data[1] & 0x20 != 0 {
+        return Err(String::from("zlib streams with a preset dictionary are not supported"));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.read_bits(1)? == 1;
+
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.read_bits(16)?;
+                let complement = reader.read_bits(16)?;
+
+                if length != !complement & 0xffff {
+                    return Err(String::from("invalid stored block length"));
+                }
+
+                for _ in 0..length {
+                    output.push(reader.read_bits(8)? as u8);
+                }
+            },
+            1 => {
+                let (literal_length, distance) = fixed_codes();
+                inflate_block(&mut reader, &literal_length, &distance, &mut output)?;
+            },
+            2 => {
+                let (literal_length, distance) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &literal_length, &distance, &mut output)?;
+            },
+            _ => return Err(String::from("invalid block type")),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    // The Adler-32 checksum follows the last block, starting at a byte
+    // boundary.
+    reader.align_to_byte();
+    let mut checksum = 0u32;
+
+    for _ in 0..4 {
+        checksum = (checksum << 8) | reader.read_bits(8)?;
+    }
+
+    if checksum != adler32(&output) {
+        return Err(String::from("zlib checksum mismatch"));
+    }
+
+    return Ok(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_block() {
+        let data = [120, 1, 1, 5, 0, 250, 255, 104, 101, 108, 108, 111, 6, 44, 2, 21];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn fixed_codes_with_matches() {
+        let data = [120, 1, 75, 76, 74, 78, 68, 66, 0, 45, 245, 5, 191];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"abcabcabcabcabc");
+    }
+
+    #[test]
+    fn truncated_stream() {
+        let data = zlib_compress(b"hello hello hello hello, a slightly longer message");
+
+        for length in 0..data.len() {
+            assert!(zlib_decompress(&data[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut data = zlib_compress(b"hello");
+        let last = data.len() - 1;
+        data[last] ^= 1;
+
+        assert!(zlib_decompress(&data).is_err());
+    }
+
+    #[test]
+    fn bad_header() {
+        assert!(zlib_decompress(&[0x78, 0x02, 3, 0, 0, 0, 0, 1]).is_err());
+        assert!(zlib_decompress(&[0x79, 0x01, 3, 0, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn distance_too_far_back() {
+        // A fixed code block that starts with a match of length 3 at
+        // distance 1.
+        let data = [0x78, 0x01, 0x03, 0x02, 0, 0, 0, 0];
+        assert_eq!(zlib_decompress(&data), Err(String::from("distance too far back")));
+    }
+}
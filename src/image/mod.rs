@@ -0,0 +1,24 @@
+mod deflate;
+mod error;
+mod exr;
+mod hdr;
+#[allow(clippy::module_inception)]
+mod image;
+mod inflate;
+mod output;
+mod pfm;
+mod png;
+mod ppm;
+mod tonemap;
+
+pub use deflate::*;
+pub use error::*;
+pub use exr::*;
+pub use hdr::*;
+pub use image::*;
+pub use inflate::*;
+pub use output::*;
+pub use pfm::*;
+pub use png::*;
+pub use ppm::*;
+pub use tonemap::*;
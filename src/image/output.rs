@@ -0,0 +1,100 @@
+use super::*;
+
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl ImageFormat {
+    // The format to write, chosen by the extension of the file name.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        return match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "exr" => Some(ImageFormat::Exr),
+            "hdr" => Some(ImageFormat::Hdr),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        };
+    }
+
+    pub fn can_read(&self) -> bool {
+        return *self != ImageFormat::Ppm;
+    }
+}
+
+// How images are encoded. The tone mapping only applies to the display
+// formats, PPM and PNG; the others store linear values.
+pub struct OutputSettings {
+    bit_depth: BitDepth,
+    exr_compression: ExrCompression,
+    tone_mapping: ToneMapping,
+}
+
+impl OutputSettings {
+    pub fn new(bit_depth: BitDepth, exr_compression: ExrCompression, tone_mapping: ToneMapping) -> OutputSettings {
+        return OutputSettings {
+            bit_depth,
+            exr_compression,
+            tone_mapping,
+        };
+    }
+
+    pub fn bit_depth(&self) -> BitDepth {
+        return self.bit_depth;
+    }
+
+    pub fn exr_compression(&self) -> ExrCompression {
+        return self.exr_compression;
+    }
+
+    pub fn tone_mapping(&self) -> &ToneMapping {
+        return &self.tone_mapping;
+    }
+}
+
+pub fn write_image(path: &Path, image: &Image, settings: &OutputSettings) -> io::Result<()> {
+    let format = match ImageFormat::from_path(path) {
+        Some(format) => format,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported file extension")),
+    };
+
+    let mut out = BufWriter::new(File::create(path)?);
+
+    return match format {
+        ImageFormat::Ppm => write_ppm(&mut out, image, settings.tone_mapping()),
+        ImageFormat::Png => write_png(&mut out, image, settings.bit_depth(), settings.tone_mapping()),
+        ImageFormat::Exr => write_exr(&mut out, image, settings.exr_compression()),
+        ImageFormat::Hdr => write_hdr(&mut out, image),
+        ImageFormat::Pfm => write_pfm(&mut out, image),
+    };
+}
+
+pub fn read_image(path: &Path) -> Result<Image, ImageError> {
+    let format = match ImageFormat::from_path(path) {
+        Some(format) if format.can_read() => format,
+        _ => return Err(ImageError::new(path, 0, String::from("only .exr, .hdr, .pfm and .png images can be read"))),
+    };
+
+    let data = std::fs::read(path).map_err(|e| ImageError::new(path, 0, e.to_string()))?;
+
+    let result = match format {
+        ImageFormat::Exr => read_exr(&data),
+        ImageFormat::Hdr => read_hdr(&data),
+        ImageFormat::Png => read_png(&data),
+        _ => read_pfm(&data),
+    };
+
+    return result.map_err(|message| ImageError::new(path, 0, message));
+}
@@ -0,0 +1,168 @@
+use super::*;
+
+use crate::math::*;
+
+use std::io;
+use std::io::Write;
+
+// Writes a little endian color Portable Float Map. Rows are stored from the
+// bottom up.
+pub fn write_pfm(out: &mut dyn Write, image: &Image) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+    for y in (0..image.height()).rev() {
+        let mut row = Vec::with_capacity(image.width() * 12);
+
+        for pixel in image.row(y) {
+            row.extend_from_slice(&(pixel.r() as f32).to_le_bytes());
+            row.extend_from_slice(&(pixel.g() as f32).to_le_bytes());
+            row.extend_from_slice(&(pixel.b() as f32).to_le_bytes());
+        }
+
+        out.write_all(&row)?;
+    }
+
+    return out.flush();
+}
+
+// Reads the next whitespace separated token of the header, which ends after
+// exactly one whitespace character.
+fn header_token<'a>(data: &'a [u8], position: &mut usize) -> Result<&'a str, String> {
+    while *position < data.len() && data[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+
+    let start = *position;
+
+    while *position < data.len() && !data[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+
+    if start == *position {
+        return Err(String::from("unexpected end of the header"));
+    }
+
+    let token = std::str::from_utf8(&data[start..*position]).map_err(|_| String::from("invalid header"))?;
+
+    if *position < data.len() {
+        *position += 1;
+    }
+
+    return Ok(token);
+}
+
+// Reads a color (PF) or greyscale (Pf) Portable Float Map. The sign of the
+// scale gives the byte order of the samples.
+pub fn read_pfm(data: &[u8]) -> Result<Image, String> {
+    let mut position = 0;
+
+    let channels = match header_token(data, &mut position)? {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(String::from("not a PFM file")),
+    };
+
+    let width: usize = header_token(data, &mut position)?.parse().map_err(|_| String::from("invalid width"))?;
+    let height: usize = header_token(data, &mut position)?.parse().map_err(|_| String::from("invalid height"))?;
+    let scale: f32 = header_token(data, &mut position)?.parse().map_err(|_| String::from("invalid scale"))?;
+
+    if width == 0 || height == 0 {
+        return Err(String::from("image has no pixels"));
+    }
+
+    let byte_count = width.checked_mul(height).and_then(|n| n.checked_mul(channels * 4)).ok_or_else(|| String::from("image is too large"))?;
+    let remaining = data.len() - position;
+
+    if remaining < byte_count {
+        return Err(format!("expected {} bytes of pixel data, found {}", byte_count, remaining));
+    }
+
+    let samples: Vec<f64> = data[position..position + byte_count]
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let value = if scale < 0.0 { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) };
+            return value as f64;
+        })
+        .collect();
+
+    if samples.iter().any(|value| !value.is_finite()) {
+        return Err(String::from("pixel data contains a value that is infinite or not a number"));
+    }
+
+    let mut image = Image::new(width, height);
+
+    for (y, row) in samples.chunks_exact(width * channels).enumerate() {
+        for (pixel, values) in image.row_mut(height - 1 - y).iter_mut().zip(row.chunks_exact(channels)) {
+            *pixel = if channels == 3 {
+                Color::new(values[0], values[1], values[2])
+            } else {
+                Color::new(values[0], values[0], values[0])
+            };
+        }
+    }
+
+    return Ok(image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> Image {
+        let mut image = Image::new(5, 3);
+
+        for y in 0..3 {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                *pixel = Color::new(x as f64 * 0.25, y as f64 * 1.5, 1000.0 / (1.0 + x as f64));
+            }
+        }
+
+        return image;
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = test_image();
+        let mut data = Vec::new();
+        write_pfm(&mut data, &image).unwrap();
+
+        let read = read_pfm(&data).unwrap();
+
+        assert_eq!((read.width(), read.height()), (5, 3));
+
+        for (a, b) in image.pixels().iter().zip(read.pixels()) {
+            assert_eq!((a.r() as f32, a.g() as f32, a.b() as f32), (b.r() as f32, b.g() as f32, b.b() as f32));
+        }
+    }
+
+    #[test]
+    fn truncated_header() {
+        assert!(read_pfm(b"PF\n1 1\n-1.0").is_err());
+        assert!(read_pfm(b"PF\n1").is_err());
+        assert!(read_pfm(b"").is_err());
+    }
+
+    #[test]
+    fn truncated_pixel_data() {
+        let mut data = Vec::new();
+        write_pfm(&mut data, &test_image()).unwrap();
+        data.truncate(data.len() - 1);
+
+        assert!(read_pfm(&data).is_err());
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        assert!(read_pfm(b"PF\n18446744073709551615 18446744073709551615\n-1.0\n").is_err());
+        assert!(read_pfm(b"PF\n1000000 1000000\n-1.0\n").is_err());
+    }
+
+    #[test]
+    fn non_finite_samples() {
+        let mut data = b"Pf\n1 1\n-1.0\n".to_vec();
+        data.extend_from_slice(&f32::NAN.to_le_bytes());
+
+        assert!(read_pfm(&data).is_err());
+    }
+}
@@ -0,0 +1,482 @@
+use super::*;
+
+use crate::math::*;
+
+use std::io;
+use std::io::Write;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+        }
+
+        *entry = c;
+    }
+
+    return table;
+}
+
+fn crc32(table: &[u32; 256], chunks: &[&[u8]]) -> u32 {
+    let mut crc = 0xffffffffu32;
+
+    for chunk in chunks {
+        for &byte in *chunk {
+            crc = table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+    }
+
+    return crc ^ 0xffffffff;
+}
+
+fn write_chunk(out: &mut dyn Write, table: &[u32; 256], chunk_type: &[u8], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(chunk_type)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(table, &[chunk_type, data]).to_be_bytes())?;
+
+    return Ok(());
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        return a;
+    } else if pb <= pc {
+        return b;
+    }
+
+    return c;
+}
+
+// Applies filter type `filter` to a scanline, given the unfiltered previous
+// scanline and the number of bytes per pixel.
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], bytes_per_pixel: usize, output: &mut Vec<u8>) {
+    output.clear();
+    output.push(filter);
+
+    for i in 0..row.len() {
+        let a = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+        let b = previous[i];
+        let c = if i >= bytes_per_pixel { previous[i - bytes_per_pixel] } else { 0 };
+
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+
+        output.push(row[i].wrapping_sub(predicted));
+    }
+}
+
+// Writes an RGB PNG file with 8 or 16 bits per channel.
+pub fn write_png(out: &mut dyn Write, image: &Image, bit_depth: BitDepth, tone_mapping: &ToneMapping) -> io::Result<()> {
+    let table = crc32_table();
+    let bytes_per_pixel = if bit_depth == BitDepth::Sixteen { 6 } else { 3 };
+    let row_length = image.width() * bytes_per_pixel;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.width() as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height() as u32).to_be_bytes());
+    header.push(if bit_depth == BitDepth::Sixteen { 16 } else { 8 });
+    // Truecolor, deflate compression, adaptive filtering, no interlacing.
+    header.extend_from_slice(&[2, 0, 0, 0]);
+
+    let mut filtered = Vec::with_capacity((row_length + 1) * image.height());
+    let mut previous = vec![0u8; row_length];
+    let mut row = Vec::with_capacity(row_length);
+    let mut candidate = Vec::with_capacity(row_length + 1);
+    let mut best = Vec::with_capacity(row_length + 1);
+
+    for y in 0..image.height() {
+        row.clear();
+
+        for &pixel in image.row(y) {
+            let display = tone_mapping.map(pixel);
+
+            for &value in &[display.r(), display.g(), display.b()] {
+                match bit_depth {
+                    BitDepth::Eight => row.push((255.0 * value).round() as u8),
+                    BitDepth::Sixteen => row.extend_from_slice(&((65535.0 * value).round() as u16).to_be_bytes()),
+                }
+            }
+        }
+
+        // Pick the filter with the smallest sum of absolute differences, the
+        // heuristic recommended by the PNG specification.
+        let mut best_score = u64::MAX;
+
+        for filter in 0..5 {
+            filter_row(filter, &row, &previous, bytes_per_pixel, &mut candidate);
+            let score: u64 = candidate[1..].iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+
+            if score < best_score {
+                best_score = score;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+
+        filtered.extend_from_slice(&best);
+        std::mem::swap(&mut previous, &mut row);
+    }
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, &table, b"IHDR", &header)?;
+    write_chunk(out, &table, b"IDAT", &zlib_compress(&filtered))?;
+    write_chunk(out, &table, b"IEND", &[])?;
+
+    return out.flush();
+}
+
+// Reverses filter type `filter` on a scanline in place, given the unfiltered
+// previous scanline and the number of bytes per pixel.
+fn unfilter_row(filter: u8, row: &mut [u8], previous: &[u8], bytes_per_pixel: usize) -> Result<(), String> {
+    if filter > 4 {
+        return Err(format!("invalid filter type {}", filter));
+    }
+
+    for i in 0..row.len() {
+        let a = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+        let b = previous[i];
+        let c = if i >= bytes_per_pixel { previous[i - bytes_per_pixel] } else { 0 };
+
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+
+        row[i] = row[i].wrapping_add(predicted);
+    }
+
+    return Ok(());
+}
+
+// Sample `index` of an unfiltered scanline, where samples of fewer than 8
+// bits are packed into bytes from the most significant bit down.
+fn sample(row: &[u8], index: usize, bit_depth: usize) -> usize {
+    return match bit_depth {
+        16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]) as usize,
+        8 => row[index] as usize,
+        _ => {
+            let bit = index * bit_depth;
+            ((row[bit / 8] >> (8 - bit_depth - bit % 8)) & ((1 << bit_depth) - 1)) as usize
+        }
+    };
+}
+
+// Reads a PNG file of any color type and bit depth, as long as it isn't
+// interlaced. The samples are assumed to be sRGB encoded and are decoded to
+// linear values; alpha is ignored.
+pub fn read_png(data: &[u8]) -> Result<Image, String> {
+    if data.len() < SIGNATURE.len() || data[..SIGNATURE.len()] != SIGNATURE {
+        return Err(String::from("not a PNG file"));
+    }
+
+    let table = crc32_table();
+    let mut position = SIGNATURE.len();
+    let mut header: Option<&[u8]> = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+
+    loop {
+        if data.len() - position < 12 {
+            return Err(String::from("unexpected end of the file"));
+        }
+
+        let length = u32::from_be_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]]) as usize;
+        let chunk_type = &data[position + 4..position + 8];
+
+        if data.len() - position - 12 < length {
+            return Err(format!("chunk '{}' runs past the end of the file", String::from_utf8_lossy(chunk_type)));
+        }
+
+        let chunk = &data[position + 8..position + 8 + length];
+        let crc = &data[position + 8 + length..position + 12 + length];
+
+        if crc32(&table, &[chunk_type, chunk]).to_be_bytes() != crc {
+            return Err(format!("chunk '{}' has a bad checksum", String::from_utf8_lossy(chunk_type)));
+        }
+
+        position += 12 + length;
+
+        match chunk_type {
+            b"IHDR" => header = Some(chunk),
+            b"PLTE" => palette = chunk,
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Ancillary chunks, with a lowercase first letter, can be skipped.
+            _ if chunk_type[0] & 0x20 != 0 => {}
+            _ => return Err(format!("unsupported chunk '{}'", String::from_utf8_lossy(chunk_type))),
+        }
+    }
+
+    let header = match header {
+        Some(header) if header.len() == 13 => header,
+        _ => return Err(String::from("missing or invalid IHDR chunk")),
+    };
+
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let bit_depth = header[8] as usize;
+    let color_type = header[9];
+
+    if width == 0 || height == 0 {
+        return Err(String::from("image has no pixels"));
+    }
+
+    let (channels, bit_depths): (usize, &[usize]) = match color_type {
+        0 => (1, &[1, 2, 4, 8, 16]),
+        2 => (3, &[8, 16]),
+        3 => (1, &[1, 2, 4, 8]),
+        4 => (2, &[8, 16]),
+        6 => (4, &[8, 16]),
+        _ => return Err(format!("invalid color type {}", color_type)),
+    };
+
+    if !bit_depths.contains(&bit_depth) {
+        return Err(format!("invalid bit depth {} for color type {}", bit_depth, color_type));
+    }
+
+    if header[10] != 0 || header[11] != 0 {
+        return Err(String::from("unsupported compression or filter method"));
+    }
+
+    if header[12] != 0 {
+        return Err(String::from("interlaced PNG images are not supported"));
+    }
+
+    if color_type == 3 && (palette.is_empty() || !palette.len().is_multiple_of(3)) {
+        return Err(String::from("missing or invalid palette"));
+    }
+
+    let bits_per_pixel = channels * bit_depth;
+    let bytes_per_pixel = (bits_per_pixel / 8).max(1);
+    let row_length = width.checked_mul(bits_per_pixel).map(|bits| bits.div_ceil(8)).ok_or_else(|| String::from("image is too large"))?;
+
+    let mut filtered = zlib_decompress(&compressed)?;
+
+    // Checked before allocating the image, so that its size is bounded by
+    // the data actually in the file.
+    if filtered.len() / (row_length + 1) < height {
+        return Err(format!("not enough image data for a {}x{} image", width, height));
+    }
+
+    let max_value = ((1usize << bit_depth) - 1) as f64;
+    let decode = |value: usize| srgb_decode(value as f64 / max_value);
+    let mut image = Image::new(width, height);
+    let mut previous = vec![0u8; row_length];
+
+    for (y, line) in filtered.chunks_exact_mut(row_length + 1).take(height).enumerate() {
+        let (filter, row) = line.split_at_mut(1);
+        unfilter_row(filter[0], row, &previous, bytes_per_pixel)?;
+
+        for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+            *pixel = match color_type {
+                0 | 4 => {
+                    let grey = decode(sample(row, x * channels, bit_depth));
+                    Color::new(grey, grey, grey)
+                }
+                3 => {
+                    let index = sample(row, x, bit_depth);
+
+                    if 3 * index + 2 >= palette.len() {
+                        return Err(format!("palette index {} out of range", index));
+                    }
+
+                    let entry = &palette[3 * index..3 * index + 3];
+                    Color::new(srgb_decode(entry[0] as f64 / 255.0), srgb_decode(entry[1] as f64 / 255.0), srgb_decode(entry[2] as f64 / 255.0))
+                }
+                _ => Color::new(
+                    decode(sample(row, x * channels, bit_depth)),
+                    decode(sample(row, x * channels + 1, bit_depth)),
+                    decode(sample(row, x * channels + 2, bit_depth)),
+                ),
+            };
+        }
+
+        previous.copy_from_slice(row);
+    }
+
+    return Ok(image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> Image {
+        let mut image = Image::new(7, 5);
+
+        for y in 0..5 {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                *pixel = Color::new(x as f64 / 6.0, y as f64 / 4.0, ((x * y) % 3) as f64 / 2.0);
+            }
+        }
+
+        return image;
+    }
+
+    fn round_trip(bit_depth: BitDepth, tolerance: f64) {
+        let image = test_image();
+        let mut data = Vec::new();
+        write_png(&mut data, &image, bit_depth, &ToneMapping::new(ToneMapOperator::Clamp, 0.0, 4.0)).unwrap();
+
+        let read = read_png(&data).unwrap();
+
+        assert_eq!((read.width(), read.height()), (7, 5));
+
+        for (a, b) in image.pixels().iter().zip(read.pixels()) {
+            assert!((a.r() - b.r()).abs() <= tolerance && (a.g() - b.g()).abs() <= tolerance && (a.b() - b.b()).abs() <= tolerance);
+        }
+    }
+
+    fn build_png(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let table = crc32_table();
+        let mut data = SIGNATURE.to_vec();
+
+        for &(chunk_type, chunk) in chunks {
+            write_chunk(&mut data, &table, chunk_type, chunk).unwrap();
+        }
+
+        return data;
+    }
+
+    fn header(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+        return header;
+    }
+
+    #[test]
+    fn round_trip_8_bit() {
+        round_trip(BitDepth::Eight, 0.01);
+    }
+
+    #[test]
+    fn round_trip_16_bit() {
+        round_trip(BitDepth::Sixteen, 1e-4);
+    }
+
+    #[test]
+    fn checksums_and_image_data() {
+        let image = test_image();
+        let mut data = Vec::new();
+        write_png(&mut data, &image, BitDepth::Eight, &ToneMapping::new(ToneMapOperator::Clamp, 0.0, 4.0)).unwrap();
+
+        assert_eq!(data[..8], SIGNATURE);
+
+        let table = crc32_table();
+        let mut position = 8;
+        let mut chunk_types = Vec::new();
+
+        while position < data.len() {
+            let length = u32::from_be_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]]) as usize;
+            let chunk_type = &data[position + 4..position + 8];
+            let chunk = &data[position + 8..position + 8 + length];
+
+            assert_eq!(crc32(&table, &[chunk_type, chunk]).to_be_bytes(), data[position + 8 + length..position + 12 + length]);
+
+            if chunk_type == b"IDAT" {
+                assert_eq!(zlib_decompress(chunk).unwrap().len(), (7 * 3 + 1) * 5);
+            }
+
+            chunk_types.push(chunk_type.to_vec());
+            position += 12 + length;
+        }
+
+        assert_eq!(chunk_types, vec![b"IHDR".to_vec(), b"IDAT".to_vec(), b"IEND".to_vec()]);
+    }
+
+    #[test]
+    fn palette_with_one_bit_indices() {
+        let row = [0, 0b1011_0000, 0b0100_0000];
+        let data = build_png(&[
+            (b"IHDR", &header(10, 1, 1, 3)),
+            (b"PLTE", &[0, 0, 0, 255, 128, 255]),
+            (b"IDAT", &zlib_compress(&row)),
+            (b"IEND", &[]),
+        ]);
+
+        let image = read_png(&data).unwrap();
+        let reds: Vec<f64> = image.row(0).iter().map(|pixel| pixel.r()).collect();
+
+        assert_eq!(reds, vec![1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!((image.row(0)[0].g() - srgb_decode(128.0 / 255.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn palette_index_out_of_range() {
+        let data = build_png(&[
+            (b"IHDR", &header(1, 1, 8, 3)),
+            (b"PLTE", &[0, 0, 0]),
+            (b"IDAT", &zlib_compress(&[0, 1])),
+            (b"IEND", &[]),
+        ]);
+
+        assert!(read_png(&data).is_err());
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut data = Vec::new();
+        write_png(&mut data, &test_image(), BitDepth::Eight, &ToneMapping::new(ToneMapOperator::Clamp, 0.0, 4.0)).unwrap();
+        data[40] ^= 1;
+
+        assert!(read_png(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut data = Vec::new();
+        write_png(&mut data, &test_image(), BitDepth::Eight, &ToneMapping::new(ToneMapOperator::Clamp, 0.0, 4.0)).unwrap();
+
+        assert!(read_png(&data[..data.len() - 5]).is_err());
+        assert!(read_png(&data[..20]).is_err());
+        assert!(read_png(&data[..4]).is_err());
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        let data = build_png(&[
+            (b"IHDR", &header(0x7fff_ffff, 0x7fff_ffff, 16, 6)),
+            (b"IDAT", &zlib_compress(&[0; 64])),
+            (b"IEND", &[]),
+        ]);
+
+        assert!(read_png(&data).is_err());
+    }
+
+    #[test]
+    fn interlaced() {
+        let mut header = header(1, 1, 8, 2);
+        header[12] = 1;
+        let data = build_png(&[(b"IHDR", &header), (b"IDAT", &zlib_compress(&[0, 0, 0, 0])), (b"IEND", &[])]);
+
+        assert!(read_png(&data).is_err());
+    }
+}
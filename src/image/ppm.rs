@@ -0,0 +1,22 @@
+use super::*;
+
+use std::io;
+use std::io::Write;
+
+// Writes a plain text (P3) PPM file with 8 bits per channel.
+pub fn write_ppm(out: &mut dyn Write, image: &Image, tone_mapping: &ToneMapping) -> io::Result<()> {
+    writeln!(out, "P3")?;
+    writeln!(out, "{} {}", image.width(), image.height())?;
+    writeln!(out, "255")?;
+
+    for &pixel_color in image.pixels() {
+        let display = tone_mapping.map(pixel_color);
+        let r = (255.0 * display.r()).round() as u8;
+        let g = (255.0 * display.g()).round() as u8;
+        let b = (255.0 * display.b()).round() as u8;
+
+        writeln!(out, "{} {} {}", r, g, b)?;
+    }
+
+    return out.flush();
+}
@@ -0,0 +1,187 @@
+use crate::math::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Hable,
+}
+
+impl ToneMapOperator {
+    pub fn from_name(name: &str) -> Option<ToneMapOperator> {
+        return match name {
+            "clamp" => Some(ToneMapOperator::Clamp),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "reinhard-extended" => Some(ToneMapOperator::ExtendedReinhard),
+            "aces" => Some(ToneMapOperator::Aces),
+            "hable" => Some(ToneMapOperator::Hable),
+            _ => None,
+        };
+    }
+}
+
+// Converts linear scene colors to display values: the exposure is applied
+// first, then the operator compresses the range to [0, 1] and finally the
+// sRGB transfer function encodes the result.
+#[derive(Clone, Copy)]
+pub struct ToneMapping {
+    operator: ToneMapOperator,
+    exposure: f64,
+    white_point: f64,
+}
+
+impl ToneMapping {
+    // The exposure is given in stops. The white point is the smallest
+    // luminance mapped to white by the extended Reinhard operator.
+    pub fn new(operator: ToneMapOperator, exposure: f64, white_point: f64) -> ToneMapping {
+        return ToneMapping {
+            operator,
+            exposure,
+            white_point,
+        };
+    }
+
+    pub fn map(&self, color: Color) -> Color {
+        let scale = 2f64.powf(self.exposure);
+        let color = Color::new(color.r().max(0.0) * scale, color.g().max(0.0) * scale, color.b().max(0.0) * scale);
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMapOperator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                scale_luminance(color, |l| l * (1.0 + l / white_squared) / (1.0 + l))
+            },
+            ToneMapOperator::Aces => map_channels(color, aces),
+            ToneMapOperator::Hable => {
+                // Uncharted 2 curve with its usual exposure bias, normalized so
+                // that the linear white point of 11.2 maps to one.
+                let white_scale = 1.0 / hable_partial(11.2);
+                map_channels(color, |x| hable_partial(2.0 * x) * white_scale)
+            },
+        };
+
+        return map_channels(mapped, |x| srgb_encode(clamp(x, 0.0, 1.0)));
+    }
+}
+
+fn luminance(color: Color) -> f64 {
+    return 0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b();
+}
+
+// Maps the luminance of a color and scales all channels by the same factor,
+// which keeps the hue unlike mapping each channel on its own.
+fn scale_luminance<F: Fn(f64) -> f64>(color: Color, f: F) -> Color {
+    let l = luminance(color);
+
+    if l <= 0.0 {
+        return Color::new_default();
+    }
+
+    let scale = f(l) / l;
+
+    return Color::new(color.r() * scale, color.g() * scale, color.b() * scale);
+}
+
+fn map_channels<F: Fn(f64) -> f64>(color: Color, f: F) -> Color {
+    return Color::new(f(color.r()), f(color.g()), f(color.b()));
+}
+
+// Narkowicz's fit of the ACES reference rendering transform.
+fn aces(x: f64) -> f64 {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+
+    return (x * (a * x + b)) / (x * (c * x + d) + e);
+}
+
+fn hable_partial(x: f64) -> f64 {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        return 12.92 * x;
+    }
+
+    return 1.055 * x.powf(1.0 / 2.4) - 0.055;
+}
+
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 {
+        return x / 12.92;
+    }
+
+    return ((x + 0.055) / 1.055).powf(2.4);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMapOperator; 5] = [ToneMapOperator::Clamp, ToneMapOperator::Reinhard, ToneMapOperator::ExtendedReinhard, ToneMapOperator::Aces, ToneMapOperator::Hable];
+
+    fn map_gray(tone_mapping: &ToneMapping, x: f64) -> f64 {
+        return tone_mapping.map(Color::new(x, x, x)).r();
+    }
+
+    #[test]
+    fn srgb_transfer() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(0.0031308) - 0.04045).abs() < 1e-6);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-12);
+
+        for i in 0..=100 {
+            let x = i as f64 / 100.0;
+            assert!((srgb_decode(srgb_encode(x)) - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn operators_are_monotonic() {
+        for operator in OPERATORS {
+            let tone_mapping = ToneMapping::new(operator, 0.0, 4.0);
+            let mut previous = map_gray(&tone_mapping, 0.0);
+
+            assert_eq!(previous, 0.0);
+
+            for i in 1..=1000 {
+                let value = map_gray(&tone_mapping, i as f64 * 0.02);
+                assert!(value >= previous && value <= 1.0);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn white_point_maps_to_white() {
+        let tone_mapping = ToneMapping::new(ToneMapOperator::ExtendedReinhard, 0.0, 4.0);
+
+        assert!((map_gray(&tone_mapping, 4.0) - 1.0).abs() < 1e-12);
+        assert!(map_gray(&tone_mapping, 3.9) < 1.0);
+    }
+
+    #[test]
+    fn exposure_in_stops() {
+        for operator in OPERATORS {
+            let brighter = ToneMapping::new(operator, 1.0, 4.0);
+            let tone_mapping = ToneMapping::new(operator, 0.0, 4.0);
+
+            for &x in &[0.01, 0.1, 0.3, 1.0, 2.5] {
+                assert!((map_gray(&brighter, x) - map_gray(&tone_mapping, 2.0 * x)).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -1,25 +1,50 @@
+#![allow(clippy::needless_return)]
+
 mod aabb;
+mod background;
+mod bvh;
+mod camera;
+mod cli;
+mod error;
 mod geometry;
+mod image;
 mod material;
 mod math;
+mod obj;
 mod random;
-
+mod render;
+mod scene;
+mod texture;
+
+use aabb::*;
+use background::*;
+use bvh::*;
+use camera::*;
 use geometry::*;
 use material::*;
 use math::*;
+use random::Sampler;
+use render::*;
+use scene::*;
 
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::time::Instant;
 
-struct HitRecord {
+struct HitRecord<'a> {
     geometry_hit_record: geometry::HitRecord,
-    material: Option<Rc<dyn Material>>,
+    material: Option<&'a dyn Material>,
+    // Index of the object that was hit in the world.
+    object: usize,
 }
 
-impl HitRecord {
-    fn new_default() -> HitRecord {
+impl<'a> HitRecord<'a> {
+    fn new_default() -> HitRecord<'a> {
         return HitRecord {
             geometry_hit_record: geometry::HitRecord::new_default(),
             material: None,
+            object: 0,
         };
     }
 
@@ -30,83 +55,232 @@ impl HitRecord {
 
 struct Entity {
     geometry: Box<dyn Geometry>,
-    material: Rc<dyn Material>,
+    materials: Vec<Arc<dyn Material>>,
 }
 
 impl Entity {
-    fn new(geometry: Box<dyn Geometry>, material: Rc<dyn Material>) -> Entity {
-        return Entity{ geometry, material };
+    fn new(geometry: Box<dyn Geometry>, material: Arc<dyn Material>) -> Entity {
+        return Entity{ geometry, materials: vec![material] };
+    }
+
+    // An entity whose geometry selects one of several materials through the
+    // material id of each hit. Ids without a material use the first one.
+    fn new_with_materials(geometry: Box<dyn Geometry>, materials: Vec<Arc<dyn Material>>) -> Entity {
+        assert!(!materials.is_empty());
+        return Entity{ geometry, materials };
     }
 
-    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn intersect<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
         if self.geometry.intersect(r, t_min, t_max, &mut rec.geometry_hit_record) {
-            rec.material = Some(self.material.clone());
+            let material = self.materials.get(rec.geometry_hit_record.material_id()).unwrap_or(&self.materials[0]);
+            rec.material = Some(material.as_ref());
             return true;
         }
 
         return false;
     }
+
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        return self.geometry.bounding_box(t0, t1, output_box);
+    }
+}
+
+// The parts of an entity with an emissive material, which are sampled
+// directly by the integrator.
+struct Light {
+    object: usize,
+    material_id: usize,
+    area: f64,
 }
 
 struct World {
     objects: Vec<Entity>,
+    bvh: Option<Bvh>,
+    bvh_objects: Vec<usize>,
+    unbounded_objects: Vec<usize>,
+    lights: Vec<Light>,
+    // The index in lights of each emissive part, by object and material id.
+    light_indices: HashMap<(usize, usize), usize>,
+    background: Background,
 }
 
 impl World {
     fn new() -> World {
         return World {
-            objects: Vec::new()
+            objects: Vec::new(),
+            bvh: None,
+            bvh_objects: Vec::new(),
+            unbounded_objects: Vec::new(),
+            lights: Vec::new(),
+            light_indices: HashMap::new(),
+            background: Background::new_default(),
         }
     }
 
+    fn background(&self) -> &Background {
+        return &self.background;
+    }
+
+    fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
     fn add(&mut self, object: Entity) {
         self.objects.push(object);
+        self.bvh = None;
+    }
+
+    // Builds a bounding volume hierarchy over all objects that can be bounded
+    // during the shutter interval [time0, time1]. Objects without a bounding
+    // box are still tested one by one. Adding objects afterwards discards the
+    // hierarchy until it is rebuilt.
+    fn build_bvh(&mut self, time0: f64, time1: f64, split_method: SplitMethod) {
+        let mut boxes = Vec::new();
+        self.bvh_objects.clear();
+        self.unbounded_objects.clear();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let mut output_box = Aabb::new_default();
+
+            if object.bounding_box(time0, time1, &mut output_box) {
+                boxes.push(output_box);
+                self.bvh_objects.push(i);
+            } else {
+                self.unbounded_objects.push(i);
+            }
+        }
+
+        self.bvh = Some(Bvh::new(&boxes, split_method));
+    }
+
+    // Collects the emissive parts of all objects whose surface can be
+    // sampled.
+    fn find_lights(&mut self) {
+        self.lights.clear();
+        self.light_indices.clear();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            for (material_id, material) in object.materials.iter().enumerate() {
+                let area = object.geometry.area(material_id);
+
+                if material.is_emissive() && area > 0.0 {
+                    self.light_indices.insert((i, material_id), self.lights.len());
+                    self.lights.push(Light { object: i, material_id, area });
+                }
+            }
+        }
+    }
+
+    fn lights(&self) -> &[Light] {
+        return &self.lights;
     }
 
-    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    // The density, with respect to solid angle at the origin of r, of
+    // direct_light picking the point where r hit the world. Zero if the hit
+    // isn't on a light.
+    fn light_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        let material_id = rec.geometry_hit_record.material_id();
+
+        let light = match self.light_indices.get(&(rec.object, material_id)) {
+            Some(&i) => &self.lights[i],
+            None => return 0.0,
+        };
+
+        let distance = rec.t() * r.direction().length();
+        let cosine = dot(rec.geometry_hit_record.geometric_normal(), unit_vector(r.direction())).abs();
+
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+
+        return self.light_point_pdf(light, distance * distance, cosine);
+    }
+
+    // The density, with respect to solid angle, of direct_light picking a
+    // point on the light distance_squared away, whose normal makes the given
+    // cosine with the direction to it.
+    fn light_point_pdf(&self, light: &Light, distance_squared: f64, cosine: f64) -> f64 {
+        return distance_squared / (cosine * light.area * self.lights.len() as f64);
+    }
+
+    fn bvh_stats(&self) -> Option<BvhStats> {
+        return self.bvh.as_ref().map(|bvh| bvh.stats());
+    }
+
+    fn intersect<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let bvh = match &self.bvh {
+            Some(bvh) => bvh,
+            None => return self.intersect_linear(r, t_min, t_max, rec),
+        };
+
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
-        for object in &self.objects {
-            if object.intersect(r, t_min, closest_so_far, &mut *rec) {
+        for &i in &self.unbounded_objects {
+            if self.objects[i].intersect(r, t_min, closest_so_far, &mut *rec) {
+                rec.object = i;
                 hit_anything = true;
                 closest_so_far = rec.t();
             }
         }
 
+        if bvh.intersect(r, t_min, closest_so_far, |i, t_min, t_max| {
+            if self.objects[self.bvh_objects[i]].intersect(r, t_min, t_max, &mut *rec) {
+                rec.object = self.bvh_objects[i];
+                return Some(rec.t());
+            }
+
+            return None;
+        }) {
+            hit_anything = true;
+        }
+
         return hit_anything;
     }
-}
 
-fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = Vec3::random_unit();
+    fn intersect_linear<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
 
-        if p.length_squared() < 1.0 {
-            return p;
+        for (i, object) in self.objects.iter().enumerate() {
+            if object.intersect(r, t_min, closest_so_far, &mut *rec) {
+                rec.object = i;
+                hit_anything = true;
+                closest_so_far = rec.t();
+            }
         }
+
+        return hit_anything;
     }
 }
 
-fn random_unit_vector() -> Vec3 {
-    let a = random::double_range(0.0, 2.0*std::f64::consts::PI);
-    let z = random::double_range(-1.0, 1.0);
+fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
+    let a = sampler.double_range(0.0, 2.0*std::f64::consts::PI);
+    let z = sampler.double_range(-1.0, 1.0);
     let r = (1.0 - z*z).sqrt();
 
     return Vec3::new(r * a.cos(), r * a.sin(), z);
 }
 
-fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(random::double_range(-1.0, 1.0), random::double_range(-1.0, 1.0), 0.0);
+fn ray_color(r: &Ray, world: &World, depth: i32, sampler: &mut Sampler) -> Color {
+    return trace(r, world, depth, 0.0, sampler);
+}
 
-        if p.length_squared() < 1.0 {
-            return p;
-        }
-    }
+// Weight of a sample from a strategy with density pdf, combined by multiple
+// importance sampling with another strategy with density other_pdf.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    return if a + b > 0.0 { a / (a + b) } else { 0.0 };
 }
 
-fn ray_color(r: &Ray, world: &World, depth: i32) -> Color {
+// Light is found both by sampling the lights directly and by scattered rays
+// that hit a light, and the two are combined with multiple importance
+// sampling. scattering_pdf is the density of the direction of r as chosen
+// at the previous hit, or zero if the light of that hit wasn't sampled
+// directly, as for camera rays and mirrors.
+fn trace(r: &Ray, world: &World, depth: i32, scattering_pdf: f64, sampler: &mut Sampler) -> Color {
     if depth <= 0 {
         return Color::new(0.0, 0.0, 0.0);
     }
@@ -114,101 +288,178 @@ fn ray_color(r: &Ray, world: &World, depth: i32) -> Color {
     let mut rec = HitRecord::new_default();
 
     if world.intersect(r, 0.001, f64::INFINITY, &mut rec) {
-        let mut scattered = Ray::new_default();
-        let mut attenuation = Color::new_default();
-
         match &rec.material {
             Some(material) => {
-                if material.scatter(r, &rec.geometry_hit_record, &mut attenuation, &mut scattered)
-                {
-                    return attenuation * ray_color(&scattered, world, depth-1);
+                let mut emitted = material.emitted(&rec.geometry_hit_record);
+
+                if scattering_pdf > 0.0 {
+                    let light_pdf = world.light_pdf(r, &rec);
+
+                    if light_pdf > 0.0 {
+                        emitted = power_heuristic(scattering_pdf, light_pdf) * emitted;
+                    }
+                }
+
+                let wo = -unit_vector(r.direction());
+
+                let sample = material.sample(&rec.geometry_hit_record, wo, sampler);
+
+                if let Some(sample) = &sample {
+                    if sample.is_specular() {
+                        let scattered = Ray::new(rec.geometry_hit_record.position(), sample.direction(), r.time());
+
+                        return emitted + sample.weight() * trace(&scattered, world, depth-1, 0.0, sampler);
+                    }
                 }
-        
-                return Color::new(0.0, 0.0, 0.0);
+
+                // The lights and the environment are still sampled when the
+                // material absorbs its own sample, since those estimates
+                // don't depend on it.
+                let direct = direct_light(r, &rec.geometry_hit_record, *material, world, sampler);
+
+                return emitted + direct + continuation_color(r, &rec.geometry_hit_record, *material, sample, world, depth, sampler);
             },
             None => panic!(),
         }
     }
 
-    let unit_direction = unit_vector(r.direction());
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    return (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
-}
-
-fn degrees_to_radians(degrees: f64) -> f64 {
-    return degrees * std::f64::consts::PI / 180.0;
-}
-
-struct Camera {
-    origin: Vec3,
-    lower_left_corner: Vec3,
-    horizontal: Vec3,
-    vertical: Vec3,
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-    lens_radius: f64,
-    time0: f64,
-    time1: f64,
-}
-
-impl Camera {
-    fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64, time0: f64, time1: f64) -> Camera {
-        let theta = degrees_to_radians(vfov);
-        let h = (theta/2.0).tan();
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
-        let w = unit_vector(look_from - look_at);
-        let u = unit_vector(cross(v_up, w));
-        let v = cross(w, u);
-
-        let origin = look_from;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let lower_left_corner = origin - horizontal/2.0 - vertical/2.0 - focus_dist * w;
-
-        Camera {
-            origin,
-            lower_left_corner,
-            horizontal,
-            vertical,
-            u,
-            v,
-            w,
-            lens_radius: aperture / 2.0,
-            time0,
-            time1,
-        }
+    return world.background().color(r.direction());
+}
+
+// The density of the direction followed after a hit on the material: the
+// material's own, or the mixture with the environment when it is
+// importance sampled.
+fn continuation_pdf(r: &Ray, rec: &geometry::HitRecord, material: &dyn Material, world: &World, direction: Vec3) -> f64 {
+    let pdf = material.pdf(rec, -unit_vector(r.direction()), direction);
+
+    return match world.background().importance_map() {
+        Some(environment) => 0.5 * pdf + 0.5 * environment.pdf(direction),
+        None => pdf,
+    };
+}
+
+// Estimates the light arriving directly from a point on one of the world's
+// lights, picked uniformly among the lights and by area on the light, by
+// casting a shadow ray towards it.
+fn direct_light(r: &Ray, rec: &geometry::HitRecord, material: &dyn Material, world: &World, sampler: &mut Sampler) -> Color {
+    let lights = world.lights();
+
+    if lights.is_empty() {
+        return Color::new_default();
+    }
+
+    let light = &lights[((sampler.double_unit() * lights.len() as f64) as usize).min(lights.len() - 1)];
+    let entity = &world.objects[light.object];
+
+    let sample = match entity.geometry.sample_surface(light.material_id, r.time(), sampler) {
+        Some(sample) => sample,
+        None => return Color::new_default(),
+    };
+
+    let to_light = sample.position() - rec.position();
+    let distance_squared = to_light.length_squared();
+    let distance = distance_squared.sqrt();
+    let direction = to_light / distance;
+    let light_cosine = dot(sample.normal(), direction).abs();
+
+    if light_cosine < 1e-8 {
+        return Color::new_default();
     }
 
-    fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * rd.x() + self.v * rd.y();
+    let f = material.eval(rec, -unit_vector(r.direction()), direction);
+
+    if f.r() <= 0.0 && f.g() <= 0.0 && f.b() <= 0.0 {
+        return Color::new_default();
+    }
+
+    // Stop the shadow ray just short of the light, so that it doesn't hit
+    // the light itself.
+    let shadow_ray = Ray::new(rec.position(), direction, r.time());
+    let mut shadow_rec = HitRecord::new_default();
+
+    if world.intersect(&shadow_ray, 0.001, distance * (1.0 - 1e-4), &mut shadow_rec) {
+        return Color::new_default();
+    }
+
+    let mut light_rec = geometry::HitRecord::new(sample.position(), sample.normal(), distance, false);
+    light_rec.set_face_normal(&shadow_ray, sample.normal());
+    light_rec.set_material_id(light.material_id);
+    let emitted = entity.materials[light.material_id].emitted(&light_rec);
+
+    // The density of the point with respect to solid angle at the hit.
+    let pdf = world.light_point_pdf(light, distance_squared, light_cosine);
+    let weight = power_heuristic(pdf, continuation_pdf(r, rec, material, world, direction));
+
+    return (weight / pdf) * (f * emitted);
+}
+
+// Follows the direction chosen by the material, if it chose one. When the
+// environment is importance sampled, follows either that direction or one
+// chosen by sampling the environment, with equal probability. Weighting by
+// the density of the mixture keeps the estimate unbiased while small bright
+// parts of the environment, like the sun, are found much more often.
+fn continuation_color(r: &Ray, rec: &geometry::HitRecord, material: &dyn Material, sample: Option<BsdfSample>, world: &World, depth: i32, sampler: &mut Sampler) -> Color {
+    let environment = match world.background().importance_map() {
+        Some(environment) => environment,
+        None => {
+            let sample = match sample {
+                Some(sample) if sample.pdf() > 0.0 => sample,
+                _ => return Color::new_default(),
+            };
+
+            let scattered = Ray::new(rec.position(), sample.direction(), r.time());
+
+            return sample.weight() * trace(&scattered, world, depth-1, sample.pdf(), sampler);
+        },
+    };
+
+    let wo = -unit_vector(r.direction());
+
+    let (direction, f) = if sampler.double_unit() < 0.5 {
+        let direction = environment.sample_direction(sampler);
+        (direction, material.eval(rec, wo, direction))
+    } else {
+        match sample {
+            Some(sample) => (sample.direction(), sample.value()),
+            None => return Color::new_default(),
+        }
+    };
 
-        return Ray::new(self.origin + offset, self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin - offset, random::double_range(self.time0, self.time1));
+    if f.r() <= 0.0 && f.g() <= 0.0 && f.b() <= 0.0 {
+        return Color::new_default();
     }
+
+    let pdf = 0.5 * material.pdf(rec, wo, direction) + 0.5 * environment.pdf(direction);
+    let scattered = Ray::new(rec.position(), direction, r.time());
+
+    return (f / pdf) * trace(&scattered, world, depth-1, pdf, sampler);
+}
+
+fn random_scene(sampler: &mut Sampler) -> World {
+    return random_scene_with_extent(11, sampler);
 }
 
-fn random_scene() -> World {
+// The layout of random_scene, with small spheres placed on a grid from
+// -extent to extent along both x and z.
+fn random_scene_with_extent(extent: i32, sampler: &mut Sampler) -> World {
     let mut world = World::new();
 
-    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
     world.add(Entity::new(Box::new(Sphere::new_stationary(Vec3::new(0.0, -1000.0, -0.0), 1000.0)), ground_material));
 
     let large_radius = 1.0;
 
-    let material1 = Rc::new(Dielectric::new(1.5));
+    let material1 = Arc::new(Dielectric::new(1.5));
     let position1 = Vec3::new(0.0, large_radius, 0.0);
     let position1_xz = Vec3::new(position1.x(), 0.0, position1.z());
     world.add(Entity::new(Box::new(Sphere::new_stationary(position1, large_radius)), material1));
 
-    let material2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
     let position2 = Vec3::new(-4.0, large_radius, 0.0);
     let position2_xz = Vec3::new(position2.x(), 0.0, position2.z());
     world.add(Entity::new(Box::new(Sphere::new_stationary(position2, large_radius)), material2));
 
-    let material3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     let position3 = Vec3::new(4.0, large_radius, 0.0);
     let position3_xz = Vec3::new(position3.x(), 0.0, position3.z());
     world.add(Entity::new(Box::new(Sphere::new_stationary(position3, large_radius)), material3));
@@ -217,10 +468,10 @@ fn random_scene() -> World {
     let min_distance = large_radius + small_radius;
     let min_distance_squared = min_distance * min_distance;
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random::double_unit();
-            let center0 = Vec3::new((a as f64) + random::double_range(0.0, 0.9), small_radius + random::double_range(0.0, 0.5), (b as f64) + random::double_range(0.0, 0.9));
+    for a in -extent..extent {
+        for b in -extent..extent {
+            let choose_mat = sampler.double_unit();
+            let center0 = Vec3::new((a as f64) + sampler.double_range(0.0, 0.9), small_radius + sampler.double_range(0.0, 0.5), (b as f64) + sampler.double_range(0.0, 0.9));
             let center0_xz = Vec3::new(center0.x(), 0.0, center0.z());
 
             let reject = (position1_xz - center0_xz).length_squared() < min_distance_squared ||
@@ -229,17 +480,17 @@ fn random_scene() -> World {
 
             if !reject {
                 let center1 = center0 + Vec3::new(0.0, 9.8, 0.0);
-                let sphere_material: Option<Rc<dyn Material>>;
+                let sphere_material: Option<Arc<dyn Material>>;
 
                 if choose_mat < 0.8 {
-                    let albedo = Color::random_unit() * Color::random_unit();
-                    sphere_material = Some(Rc::new(Lambertian::new(albedo)));
+                    let albedo = Color::random_unit(sampler) * Color::random_unit(sampler);
+                    sphere_material = Some(Arc::new(Lambertian::new(albedo)));
                 } else if choose_mat < 0.95 {
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = random::double_range(0.0, 0.5);
-                    sphere_material = Some(Rc::new(Metal::new(albedo, fuzz)));
+                    let albedo = Color::random_range(0.5, 1.0, sampler);
+                    let fuzz = sampler.double_range(0.0, 0.5);
+                    sphere_material = Some(Arc::new(Metal::new(albedo, fuzz)));
                 } else {
-                    sphere_material = Some(Rc::new(Dielectric::new(1.5)));
+                    sphere_material = Some(Arc::new(Dielectric::new(1.5)));
                 }
 
                 match sphere_material {
@@ -253,44 +504,442 @@ fn random_scene() -> World {
     return world;
 }
 
-fn main() {
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width = 2048;
-    let image_height = ((image_width as f64) / aspect_ratio) as i32;
-    let samples_per_pixel = 100;
-    let max_depth = 50;
+const ICOSAHEDRON_FACES: [[u32; 3]; 20] = [
+    [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+    [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+    [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+    [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+];
+
+fn icosahedron_normals() -> Vec<Vec3> {
+    let p = (1.0 + 5.0_f64.sqrt()) / 2.0;
+
+    return vec![
+        Vec3::new(-1.0, p, 0.0), Vec3::new(1.0, p, 0.0), Vec3::new(-1.0, -p, 0.0), Vec3::new(1.0, -p, 0.0),
+        Vec3::new(0.0, -1.0, p), Vec3::new(0.0, 1.0, p), Vec3::new(0.0, -1.0, -p), Vec3::new(0.0, 1.0, -p),
+        Vec3::new(p, 0.0, -1.0), Vec3::new(p, 0.0, 1.0), Vec3::new(-p, 0.0, -1.0), Vec3::new(-p, 0.0, 1.0),
+    ].into_iter().map(unit_vector).collect();
+}
+
+// An icosahedron mesh whose faces alternate between material ids 0 and 1.
+fn icosahedron_mesh(center: Vec3, radius: f64, smooth: bool) -> TriangleMesh {
+    let normals = icosahedron_normals();
+    let positions = normals.iter().map(|&n| center + radius * n).collect();
 
-    println!("P3");
-    println!("{} {}", image_width, image_height);
-    println!("255");
+    let faces = ICOSAHEDRON_FACES.iter().enumerate().map(|(i, &face)| {
+        return MeshFace::new(face, if smooth { Some(face) } else { None }, None, (i % 2) as u32);
+    }).collect();
 
-    let world = random_scene();
+    return TriangleMesh::new(positions, if smooth { normals } else { Vec::new() }, Vec::new(), faces).unwrap();
+}
 
+// An icosahedron made of separate triangles rather than a mesh.
+fn add_icosahedron_triangles(world: &mut World, center: Vec3, radius: f64, material: Arc<dyn Material>) {
+    let normals = icosahedron_normals();
+
+    for face in &ICOSAHEDRON_FACES {
+        let n = [normals[face[0] as usize], normals[face[1] as usize], normals[face[2] as usize]];
+        let v = [center + radius * n[0], center + radius * n[1], center + radius * n[2]];
+
+        world.add(Entity::new(Box::new(Triangle::new_with_normals(v[0], v[1], v[2], n[0], n[1], n[2])), material.clone()));
+    }
+}
+
+// Flat and smooth shaded icosahedra, both as meshes and separate triangles,
+// framed by the random_scene camera.
+fn triangle_scene() -> World {
+    let mut world = World::new();
+
+    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Entity::new(Box::new(Sphere::new_stationary(Vec3::new(0.0, -1000.0, -0.0), 1000.0)), ground_material));
+
+    let brown: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+    world.add(Entity::new_with_materials(Box::new(icosahedron_mesh(Vec3::new(-4.0, 1.0, 0.0), 1.0, false)), vec![brown, white]));
+
+    let glass = Arc::new(Dielectric::new(1.5));
+    world.add(Entity::new(Box::new(icosahedron_mesh(Vec3::new(0.0, 1.0, 0.0), 1.0, true)), glass));
+
+    add_icosahedron_triangles(&mut world, Vec3::new(4.0, 1.0, 0.0), 1.0, Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)));
+
+    let floor_material = Arc::new(Lambertian::new(Color::new(0.2, 0.3, 0.1)));
+    let a = Vec3::new(-6.0, 0.01, -3.0);
+    let b = Vec3::new(6.0, 0.01, -3.0);
+    let c = Vec3::new(6.0, 0.01, 3.0);
+    let d = Vec3::new(-6.0, 0.01, 3.0);
+    world.add(Entity::new(Box::new(Triangle::new(a, c, b)), floor_material.clone()));
+    world.add(Entity::new(Box::new(Triangle::new(a, d, c)), floor_material));
+
+    return world;
+}
+
+// A box with one corner at the origin, rotated by angle degrees about the y
+// axis and then moved by offset.
+fn box_mesh(size: Vec3, angle: f64, offset: Vec3) -> TriangleMesh {
+    let (sin_theta, cos_theta) = degrees_to_radians(angle).sin_cos();
+
+    // Bit 0 of the corner index selects the x coordinate, bit 1 y and bit 2 z.
+    let positions = (0..8).map(|i| {
+        let x = if i & 1 != 0 { size.x() } else { 0.0 };
+        let y = if i & 2 != 0 { size.y() } else { 0.0 };
+        let z = if i & 4 != 0 { size.z() } else { 0.0 };
+        return offset + Vec3::new(cos_theta * x + sin_theta * z, y, -sin_theta * x + cos_theta * z);
+    }).collect();
+
+    let quads = [[0, 4, 6, 2], [1, 3, 7, 5], [0, 1, 5, 4], [2, 6, 7, 3], [0, 2, 3, 1], [4, 5, 7, 6]];
+    let mut faces = Vec::new();
+
+    for q in &quads {
+        faces.push(MeshFace::new([q[0], q[1], q[2]], None, None, 0));
+        faces.push(MeshFace::new([q[0], q[2], q[3]], None, None, 0));
+    }
+
+    return TriangleMesh::new(positions, Vec::new(), Vec::new(), faces).unwrap();
+}
+
+// The Cornell box with two white blocks, lit only by the square light in the
+// ceiling.
+fn cornell_box_scene() -> World {
+    let mut world = World::new();
+    world.set_background(Background::Solid(Color::new_default()));
+
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+
+    // The walls and the light form one mesh, with material ids 0 to 3 for
+    // white, red, green and the light.
+    let positions = vec![
+        Vec3::new(0.0, 0.0, 0.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(555.0, 0.0, 555.0), Vec3::new(0.0, 0.0, 555.0),
+        Vec3::new(0.0, 555.0, 0.0), Vec3::new(555.0, 555.0, 0.0), Vec3::new(555.0, 555.0, 555.0), Vec3::new(0.0, 555.0, 555.0),
+        Vec3::new(213.0, 554.0, 227.0), Vec3::new(343.0, 554.0, 227.0), Vec3::new(343.0, 554.0, 332.0), Vec3::new(213.0, 554.0, 332.0),
+    ];
+
+    let quads = [
+        ([0, 1, 2, 3], 0), // floor
+        ([4, 7, 6, 5], 0), // ceiling
+        ([3, 2, 6, 7], 0), // back
+        ([1, 5, 6, 2], 1), // left, seen from the camera
+        ([0, 3, 7, 4], 2), // right
+        ([8, 11, 10, 9], 3), // light
+    ];
+
+    let mut faces = Vec::new();
+
+    for (q, material_id) in &quads {
+        faces.push(MeshFace::new([q[0], q[1], q[2]], None, None, *material_id));
+        faces.push(MeshFace::new([q[0], q[2], q[3]], None, None, *material_id));
+    }
+
+    let walls = TriangleMesh::new(positions, Vec::new(), Vec::new(), faces).unwrap();
+    world.add(Entity::new_with_materials(Box::new(walls), vec![white.clone(), red, green, light]));
+
+    world.add(Entity::new(Box::new(box_mesh(Vec3::new(165.0, 330.0, 165.0), 15.0, Vec3::new(265.0, 0.0, 295.0))), white.clone()));
+    world.add(Entity::new(Box::new(box_mesh(Vec3::new(165.0, 165.0, 165.0), -18.0, Vec3::new(130.0, 0.0, 65.0))), white));
+
+    return world;
+}
+
+fn cornell_box_camera() -> Camera {
+    let look_from = Vec3::new(278.0, 278.0, -800.0);
+    let look_at = Vec3::new(278.0, 278.0, 0.0);
+    let v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    return Camera::new(look_from, look_at, v_up, 40.0, 1.0, 0.0, 10.0, 0.0, 0.0);
+}
+
+// The contents of an OBJ file, seen from the same direction as random_scene.
+fn obj_scene(path: &std::path::Path) -> Result<World, obj::ObjError> {
+    let mut world = World::new();
+
+    for entity in obj::load_obj(path)? {
+        world.add(entity);
+    }
+
+    return Ok(world);
+}
+
+// A camera looking at the center of the world's bounding box from the same
+// direction as the random_scene camera, far enough away to see all of it.
+fn framing_camera(world: &World, aspect_ratio: f64) -> Camera {
+    let mut bounds: Option<Aabb> = None;
+
+    for object in &world.objects {
+        let mut output_box = Aabb::new_default();
+
+        if object.bounding_box(0.0, 0.0, &mut output_box) {
+            bounds = Some(match bounds {
+                Some(b) => surrounding_box(&b, &output_box),
+                None => output_box,
+            });
+        }
+    }
+
+    let bounds = bounds.unwrap_or_else(|| Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)));
+    let radius = 0.5 * bounds.extent().length();
+    let vfov = 20.0;
+    let distance = 1.1 * radius / degrees_to_radians(vfov / 2.0).sin();
+
+    let look_at = bounds.centroid();
+    let look_from = look_at + distance * unit_vector(Vec3::new(13.0, 2.0, 3.0));
+    let v_up = Vec3::new(0.0, 1.0, 0.0);
+
+    return Camera::new(look_from, look_at, v_up, vfov, aspect_ratio, 0.0, distance, 0.0, 0.0);
+}
+
+fn random_scene_camera(aspect_ratio: f64) -> Camera {
     let look_from = Vec3::new(13.0, 2.0, 3.0);
     let look_at = Vec3::new(0.0, 0.0, 0.0);
     let v_up = Vec3::new(0.0, 1.0, 0.0);
     let dist_to_focus = 10.0;
     let aperture = 0.1;
-    let cam = Camera::new(look_from, look_at, v_up, 20.0, aspect_ratio, aperture, dist_to_focus, 0.0, 1.0 / 60.0);
 
-    for j in (0..image_height).rev() {
-        eprint!("\rScanlines remaining: {} ", j);
+    return Camera::new(look_from, look_at, v_up, 20.0, aspect_ratio, aperture, dist_to_focus, 0.0, 1.0 / 60.0);
+}
+
+// Compares the build time, tree shape and primary ray throughput of the
+// midpoint and surface area heuristic builders on the random_scene layout.
+fn bvh_benchmark() {
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 640;
+    let image_height = ((image_width as f64) / aspect_ratio) as i32;
+    let cam = random_scene_camera(aspect_ratio);
 
-        for i in 0..image_width {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+    for &extent in &[11, 110] {
+        let mut world = random_scene_with_extent(extent, &mut Sampler::new(0));
+        println!("random_scene layout with extent {}: {} objects", extent, world.objects.len());
 
-            for _ in 0..samples_per_pixel {
-                let u = ((i as f64) + random::double_unit()) / ((image_width-1) as f64);
-                let v = ((j as f64) + random::double_unit()) / ((image_height-1) as f64);
-    
-                pixel_color += ray_color(&cam.get_ray(u, v), &world, max_depth);
+        for &(name, split_method) in &[("midpoint", SplitMethod::Midpoint), ("sah", SplitMethod::Sah)] {
+            let build_start = Instant::now();
+            world.build_bvh(cam.time0(), cam.time1(), split_method);
+            let build_time = build_start.elapsed();
+
+            if let Some(stats) = world.bvh_stats() {
+                println!("  {:>8}: built in {:.2?}, {}", name, build_time, stats);
             }
 
-            pixel_color /= samples_per_pixel as f64;
+            // Both builders are timed on exactly the same rays.
+            let mut sampler = Sampler::new(0);
+            let trace_start = Instant::now();
+            let mut hits = 0;
+
+            for j in 0..image_height {
+                for i in 0..image_width {
+                    let u = (i as f64) / ((image_width-1) as f64);
+                    let v = (j as f64) / ((image_height-1) as f64);
+                    let mut rec = HitRecord::new_default();
 
-            println!("{}", pixel_color);
+                    if world.intersect(&cam.get_ray(u, v, &mut sampler), 0.001, f64::INFINITY, &mut rec) {
+                        hits += 1;
+                    }
+                }
+            }
+
+            let trace_time = trace_start.elapsed();
+            let ray_count = (image_width * image_height) as f64;
+
+            println!("  {:>8}: traced {} rays in {:.2?} ({:.2} Mrays/s, {} hits)", name, ray_count, trace_time, ray_count / trace_time.as_secs_f64() / 1.0e6, hits);
         }
     }
+}
+
+// The built-in scenes, rendered with the settings main() used to hard-code.
+fn builtin_scene(name: &str) -> Option<Scene> {
+    let image_width = 2048;
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let seed = 0;
+
+    let (world, cam) = match name {
+        "random" => (random_scene(&mut Sampler::new(seed)), random_scene_camera(16.0 / 9.0)),
+        "triangles" => (triangle_scene(), random_scene_camera(16.0 / 9.0)),
+        "cornell" => (cornell_box_scene(), cornell_box_camera()),
+        _ => return None,
+    };
+
+    let image_height = ((image_width as f64) / cam.aspect_ratio()) as i32;
+    let settings = RenderSettings::new(image_width, image_height, samples_per_pixel, max_depth, seed);
+
+    return Some(Scene::new(world, cam, settings));
+}
+
+fn load_scene_or_exit(options: &cli::Options) -> Scene {
+    if let Some(obj_path) = options.obj() {
+        return match obj_scene(obj_path) {
+            Ok(world) => {
+                let aspect_ratio = 16.0 / 9.0;
+                let cam = framing_camera(&world, aspect_ratio);
+                let settings = RenderSettings::new(2048, (2048.0 / aspect_ratio) as i32, 100, 50, 0);
+                Scene::new(world, cam, settings)
+            },
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            },
+        };
+    }
+
+    let name = options.scene().unwrap_or("random");
+
+    // Anything that isn't the name of a built-in scene is a scene file.
+    if let Some(scene) = builtin_scene(name) {
+        return scene;
+    }
+
+    return match load_scene(std::path::Path::new(name)) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        },
+    };
+}
+
+// Replaces the render settings of the scene with those given on the command
+// line. Giving both width and height changes the aspect ratio of the camera.
+fn apply_options(scene: &mut Scene, options: &cli::Options) {
+    let settings = scene.settings();
+    let aspect_ratio = scene.camera().aspect_ratio();
+
+    let (image_width, image_height) = match (options.width(), options.height()) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, (((width as f64) / aspect_ratio) as i32).max(1)),
+        (None, Some(height)) => ((((height as f64) * aspect_ratio) as i32).max(1), height),
+        (None, None) => (settings.image_width(), settings.image_height()),
+    };
+
+    let settings = RenderSettings::new(
+        image_width,
+        image_height,
+        options.samples_per_pixel().unwrap_or_else(|| settings.samples_per_pixel()),
+        options.max_depth().unwrap_or_else(|| settings.max_depth()),
+        options.seed().unwrap_or_else(|| settings.seed()),
+    );
+
+    if options.width().is_some() && options.height().is_some() {
+        scene.set_aspect_ratio((image_width as f64) / (image_height as f64));
+    }
+
+    scene.set_settings(settings);
+}
+
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli::Command::Render(options)) => options,
+        Ok(cli::Command::BenchBvh) => {
+            bvh_benchmark();
+            return;
+        },
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\nRun with --help for usage.", e);
+            std::process::exit(2);
+        },
+    };
+
+    let mut scene = load_scene_or_exit(&options);
+    apply_options(&mut scene, &options);
+
+    let reference = options.compare().map(|path| match image::read_image(path) {
+        Ok(reference) => reference,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        },
+    });
+
+    let thread_count = options.threads().unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let settings = scene.settings();
+
+    if let Some(reference) = &reference {
+        if reference.width() != settings.image_width() as usize || reference.height() != settings.image_height() as usize {
+            eprintln!(
+                "error: the reference image is {}x{}, but the render is {}x{}",
+                reference.width(), reference.height(), settings.image_width(), settings.image_height()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let framebuffer = render(scene.world(), scene.camera(), settings, thread_count);
+
+    let tone_mapping = image::ToneMapping::new(options.tone_map_operator(), options.exposure(), options.white_point());
+    let output_settings = image::OutputSettings::new(options.bit_depth(), options.exr_compression(), tone_mapping);
+
+    let result = match options.output() {
+        Some(path) => image::write_image(path, &framebuffer, &output_settings),
+        None => image::write_ppm(&mut BufWriter::new(std::io::stdout().lock()), &framebuffer, output_settings.tone_mapping()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("\nerror: could not write the image: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(reference) = &reference {
+        eprintln!("\nRMS error against the reference: {}", framebuffer.root_mean_square_error(reference));
+    }
 
     eprintln!("\nDone.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The density light_pdf gives a ray hitting a point on a sphere light has
+    // to be the one direct_light uses when it picks that point.
+    #[test]
+    fn light_pdf_matches_direct_light() {
+        let mut world = World::new();
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Entity::new(Box::new(Sphere::new_stationary(Vec3::new(0.0, -1000.0, 0.0), 1000.0)), white));
+        world.add(Entity::new(Box::new(Sphere::new_stationary(Vec3::new(-3.0, 4.0, 0.0), 0.5)), Arc::new(DiffuseLight::new(Color::new(2.0, 2.0, 2.0)))));
+        world.add(Entity::new(Box::new(Sphere::new_stationary(Vec3::new(1.0, 5.0, 2.0), 1.0)), Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)))));
+        world.find_lights();
+
+        assert_eq!(world.lights().len(), 2);
+
+        let position = Vec3::new(0.0, 0.0, 0.0);
+        let mut sampler = Sampler::new(5);
+        let mut compared = 0;
+
+        for light in world.lights() {
+            for _ in 0..100 {
+                let sample = world.objects[light.object].geometry.sample_surface(light.material_id, 0.0, &mut sampler).unwrap();
+                let to_light = sample.position() - position;
+                let direction = unit_vector(to_light);
+
+                // Points on the far side of the sphere can't be reached by a
+                // ray from the position.
+                if dot(sample.normal(), direction) > -0.01 {
+                    continue;
+                }
+
+                let expected = world.light_point_pdf(light, to_light.length_squared(), dot(sample.normal(), direction).abs());
+
+                // A direction that isn't a unit vector, as rays may have.
+                let r = Ray::new(position, 0.5 * to_light, 0.0);
+                let mut rec = HitRecord::new_default();
+                assert!(world.intersect(&r, 0.001, f64::INFINITY, &mut rec));
+                assert_eq!(rec.object, light.object);
+
+                let pdf = world.light_pdf(&r, &rec);
+                assert!((pdf - expected).abs() < 1e-9 * expected, "{} != {}", pdf, expected);
+                compared += 1;
+            }
+        }
+
+        assert!(compared > 50);
+
+        // Hits that aren't on a light aren't picked by direct_light.
+        let r = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let mut rec = HitRecord::new_default();
+        assert!(world.intersect(&r, 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(world.light_pdf(&r, &rec), 0.0);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::math::*;
+
+// A direction chosen by Material::sample, together with the value of the
+// BSDF times the cosine at the surface for that direction, and the density
+// it was chosen with. For specular samples, from mirrors and glass, the
+// value is already divided by the probability of the choice and the pdf is
+// one, since no other direction could have been chosen.
+pub struct BsdfSample {
+    direction: Vec3,
+    value: Color,
+    pdf: f64,
+    specular: bool,
+}
+
+impl BsdfSample {
+    pub fn new(direction: Vec3, value: Color, pdf: f64) -> BsdfSample {
+        return BsdfSample { direction, value, pdf, specular: false };
+    }
+
+    pub fn new_specular(direction: Vec3, value: Color) -> BsdfSample {
+        return BsdfSample { direction, value, pdf: 1.0, specular: true };
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        return self.direction;
+    }
+
+    pub fn value(&self) -> Color {
+        return self.value;
+    }
+
+    pub fn pdf(&self) -> f64 {
+        return self.pdf;
+    }
+
+    pub fn is_specular(&self) -> bool {
+        return self.specular;
+    }
+
+    // The factor by which the light arriving along the direction is
+    // scaled.
+    pub fn weight(&self) -> Color {
+        return self.value / self.pdf;
+    }
+}
@@ -1,12 +1,10 @@
 use super::*;
 
-use crate::unit_vector;
-
 use crate::geometry::HitRecord;
 
 use crate::math::*;
 
-use crate::random;
+use crate::random::Sampler;
 
 pub struct Dielectric {
     refraction_index: f64,
@@ -19,36 +17,67 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        *attenuation = Color::new(1.0, 1.0, 1.0);
-        let etai_over_etat: f64;
-
-        if rec.front_face() {
-            etai_over_etat = 1.0 / self.refraction_index;
+    // Reflects or refracts, choosing reflection with the probability given
+    // by the Fresnel equations, so that both carry all of the light.
+    fn sample(&self, rec: &HitRecord, wo: Vec3, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let etai_over_etat = if rec.front_face() {
+            1.0 / self.refraction_index
         } else {
-            etai_over_etat = self.refraction_index;
-        }
+            self.refraction_index
+        };
 
-        let unit_direction = unit_vector(r_in.direction());
-        let cos_theta = f64::min(dot(-unit_direction, rec.normal()), 1.0);
+        let unit_direction = -wo;
+        let cos_theta = f64::min(dot(wo, rec.normal()), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         if (etai_over_etat * sin_theta) > 1.0 {
-            let reflected = reflect(unit_direction, rec.normal());
-            *scattered = Ray::new(rec.position(), reflected, r_in.time());
-            return true;
+            return Some(BsdfSample::new_specular(reflect(unit_direction, rec.normal()), white));
         }
 
         let reflect_prob = schlick(cos_theta, etai_over_etat);
-        if random::double_unit() < reflect_prob {
-            let reflected = reflect(unit_direction, rec.normal());
-            *scattered = Ray::new(rec.position(), reflected, r_in.time());
-            return true;
+        if sampler.double_unit() < reflect_prob {
+            return Some(BsdfSample::new_specular(reflect(unit_direction, rec.normal()), white));
         }
 
         let refracted = refract(unit_direction, rec.normal(), etai_over_etat);
-        *scattered = Ray::new(rec.position(), refracted, r_in.time());
 
-        return true;
+        return Some(BsdfSample::new_specular(unit_vector(refracted), white));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_specular() {
+        let material = Dielectric::new(1.5);
+        let mut sampler = Sampler::new(2);
+        let wo = unit_vector(Vec3::new(0.5, 0.0, 1.0));
+        let (mut reflected, mut refracted) = (0, 0);
+
+        for front_face in [true, false] {
+            let rec = HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 1.0, front_face);
+
+            for _ in 0..1000 {
+                let sample = material.sample(&rec, wo, &mut sampler).unwrap();
+                let wi = sample.direction();
+
+                assert!(sample.is_specular());
+                assert_eq!(sample.pdf(), 1.0);
+                assert_eq!(material.pdf(&rec, wo, wi), 0.0);
+                assert_eq!(material.eval(&rec, wo, wi).r(), 0.0);
+                assert!((wi.length() - 1.0).abs() < 1e-12);
+
+                if wi.z() > 0.0 {
+                    reflected += 1;
+                } else {
+                    refracted += 1;
+                }
+            }
+        }
+
+        assert!(reflected > 0 && refracted > 0);
     }
 }
@@ -0,0 +1,35 @@
+use super::*;
+
+use crate::geometry::HitRecord;
+
+use crate::math::*;
+
+use crate::random::Sampler;
+
+// A light source that emits the same radiance in all directions from both
+// sides of the surface, and does not reflect any light.
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        return DiffuseLight {
+            emit,
+        };
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _rec: &HitRecord, _wo: Vec3, _sampler: &mut Sampler) -> Option<BsdfSample> {
+        return None;
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        return self.emit;
+    }
+
+    fn is_emissive(&self) -> bool {
+        return true;
+    }
+}
@@ -6,12 +6,23 @@ use crate::geometry::HitRecord;
 
 use crate::math::*;
 
+use crate::random::Sampler;
+
+use crate::texture::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
+        return Lambertian::new_textured(Arc::new(SolidColor::new(albedo)));
+    }
+
+    pub fn new_textured(albedo: Arc<dyn Texture>) -> Lambertian {
         return Lambertian {
             albedo,
         };
@@ -19,11 +30,63 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        let scatter_direction = rec.normal() + random_unit_vector();
-        *scattered = Ray::new(rec.position(), scatter_direction, r_in.time());
-        *attenuation = self.albedo;
+    // Directions follow a cosine distribution about the normal.
+    fn sample(&self, rec: &HitRecord, wo: Vec3, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let mut direction = rec.normal() + random_unit_vector(sampler);
+
+        // The random vector may cancel out the normal.
+        if direction.length_squared() < 1e-16 {
+            direction = rec.normal();
+        }
+
+        let wi = unit_vector(direction);
+
+        return Some(BsdfSample::new(wi, self.eval(rec, wo, wi), self.pdf(rec, wo, wi)));
+    }
+
+    fn eval(&self, rec: &HitRecord, _wo: Vec3, wi: Vec3) -> Color {
+        let cosine = dot(rec.normal(), wi);
+
+        if cosine <= 0.0 {
+            return Color::new_default();
+        }
+
+        let (u, v) = rec.uv();
+
+        return (cosine / PI) * self.albedo.value(u, v, rec.object_position());
+    }
+
+    fn pdf(&self, rec: &HitRecord, _wo: Vec3, wi: Vec3) -> f64 {
+        let cosine = dot(rec.normal(), wi);
+
+        return if cosine > 0.0 { cosine / PI } else { 0.0 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        let material = Lambertian::new(Color::new(0.5, 0.6, 0.7));
+        let rec = HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 1.0, true);
+        let wo = unit_vector(Vec3::new(0.3, -0.2, 1.0));
+        let mut sampler = Sampler::new(2);
+
+        for _ in 0..1000 {
+            let sample = material.sample(&rec, wo, &mut sampler).unwrap();
+            let wi = sample.direction();
+            let value = material.eval(&rec, wo, wi);
+
+            assert!(!sample.is_specular());
+            assert!(dot(wi, rec.normal()) >= 0.0);
+            assert!((sample.pdf() - material.pdf(&rec, wo, wi)).abs() < 1e-12);
+            assert!((sample.value().r() - value.r()).abs() < 1e-12 && (sample.value().g() - value.g()).abs() < 1e-12 && (sample.value().b() - value.b()).abs() < 1e-12);
+        }
 
-        return true;
+        // Nothing is scattered below the surface.
+        assert_eq!(material.pdf(&rec, wo, Vec3::new(0.0, 0.6, -0.8)), 0.0);
+        assert_eq!(material.eval(&rec, wo, Vec3::new(0.0, 0.6, -0.8)).r(), 0.0);
     }
 }
@@ -1,7 +1,40 @@
+use super::*;
+
 use crate::geometry::HitRecord;
 
 use crate::math::*;
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool;
+use crate::random::Sampler;
+
+// Directions are unit vectors pointing away from the hit point: wo towards
+// the viewer, wi towards where the light comes from.
+pub trait Material: Send + Sync {
+    // Picks the direction wi to follow from the hit point. None if the light
+    // is absorbed.
+    fn sample(&self, rec: &HitRecord, wo: Vec3, sampler: &mut Sampler) -> Option<BsdfSample>;
+
+    // The BSDF times the cosine at the surface, the fraction of light
+    // arriving from wi that is scattered towards wo. Zero for specular
+    // materials, which only scatter into single directions.
+    fn eval(&self, _rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        return Color::new_default();
+    }
+
+    // The probability density, with respect to solid angle, of sample
+    // choosing wi. Zero for specular materials.
+    fn pdf(&self, _rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        return 0.0;
+    }
+
+    // Radiance emitted by the surface at the hit point. Most materials
+    // don't emit any light.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        return Color::new_default();
+    }
+
+    // Whether the surface emits light, which makes it a light of the world
+    // when its geometry can be sampled.
+    fn is_emissive(&self) -> bool {
+        return false;
+    }
 }
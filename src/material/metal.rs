@@ -1,28 +1,145 @@
 use super::*;
 
-use crate::random_in_unit_sphere;
-
 use crate::geometry::HitRecord;
 
 use crate::math::*;
 
+use crate::random::Sampler;
+
+use crate::texture::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// A metal reflects light around the mirror direction. Without fuzzing it is
+// a perfect mirror, otherwise the reflection is spread over a Phong lobe
+// whose width grows with the fuzzing.
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzzing: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzzing: f64) -> Metal {
+        return Metal::new_textured(Arc::new(SolidColor::new(albedo)), fuzzing);
+    }
+
+    pub fn new_textured(albedo: Arc<dyn Texture>, fuzzing: f64) -> Metal {
         return Metal{ albedo, fuzzing };
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let (u, v) = rec.uv();
+
+        return self.albedo.value(u, v, rec.object_position());
+    }
+
+    // An exponent giving about the same spread as perturbing the mirror
+    // direction by the fuzzing times a random point in the unit sphere.
+    fn exponent(&self) -> f64 {
+        return 5.0 / (self.fuzzing * self.fuzzing);
+    }
+
+    // Density of the lobe about the mirror direction, with respect to solid
+    // angle.
+    fn lobe_pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let cosine = dot(reflect(-wo, rec.normal()), wi);
+
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+
+        let exponent = self.exponent();
+
+        return (exponent + 1.0) / (2.0 * PI) * cosine.powf(exponent);
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        let reflected = reflect(unit_vector(r_in.direction()), rec.normal());
-        *scattered = Ray::new(rec.position(), reflected + self.fuzzing * random_in_unit_sphere(), r_in.time());
-        *attenuation = self.albedo;
+    fn sample(&self, rec: &HitRecord, wo: Vec3, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let reflected = reflect(-wo, rec.normal());
+
+        if self.fuzzing <= 0.0 {
+            return Some(BsdfSample::new_specular(reflected, self.albedo(rec)));
+        }
+
+        let cos_theta = sampler.double_unit().powf(1.0 / (self.exponent() + 1.0));
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.double_unit();
+        let wi = unit_vector(Onb::new_from_w(reflected).local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+
+        // Directions below the surface are absorbed.
+        if dot(wi, rec.normal()) <= 0.0 {
+            return None;
+        }
+
+        return Some(BsdfSample::new(wi, self.eval(rec, wo, wi), self.pdf(rec, wo, wi)));
+    }
+
+    // The lobe includes the cosine factor, so that sampled directions carry
+    // the albedo.
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if self.fuzzing <= 0.0 || dot(wi, rec.normal()) <= 0.0 {
+            return Color::new_default();
+        }
+
+        return self.lobe_pdf(rec, wo, wi) * self.albedo(rec);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if self.fuzzing <= 0.0 {
+            return 0.0;
+        }
+
+        return self.lobe_pdf(rec, wo, wi);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_record() -> HitRecord {
+        return HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 1.0, true);
+    }
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        let material = Metal::new(Color::new(0.8, 0.7, 0.6), 0.3);
+        let rec = test_record();
+        let wo = unit_vector(Vec3::new(0.5, 0.0, 1.0));
+        let mut sampler = Sampler::new(2);
+        let mut count = 0;
+
+        for _ in 0..1000 {
+            let sample = match material.sample(&rec, wo, &mut sampler) {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            let wi = sample.direction();
+            let value = material.eval(&rec, wo, wi);
+
+            assert!(!sample.is_specular());
+            assert!(sample.pdf() > 0.0);
+            assert!((sample.pdf() - material.pdf(&rec, wo, wi)).abs() < 1e-9 * sample.pdf());
+            assert!((sample.value().r() - value.r()).abs() < 1e-9 && (sample.value().g() - value.g()).abs() < 1e-9 && (sample.value().b() - value.b()).abs() < 1e-9);
+            count += 1;
+        }
+
+        assert!(count > 900);
+    }
+
+    #[test]
+    fn mirror() {
+        let material = Metal::new(Color::new(0.8, 0.7, 0.6), 0.0);
+        let rec = test_record();
+        let wo = unit_vector(Vec3::new(0.5, 0.0, 1.0));
+        let sample = material.sample(&rec, wo, &mut Sampler::new(2)).unwrap();
 
-        return dot(scattered.direction(), rec.normal()) > 0.0;
+        assert!(sample.is_specular());
+        assert!((sample.direction().x() + wo.x()).abs() < 1e-12 && (sample.direction().z() - wo.z()).abs() < 1e-12);
+        assert_eq!(material.pdf(&rec, wo, sample.direction()), 0.0);
+        assert_eq!(material.eval(&rec, wo, sample.direction()).r(), 0.0);
     }
 }
@@ -1,9 +1,14 @@
+#[allow(clippy::module_inception)]
 pub mod material;
+pub mod bsdfsample;
 pub mod dielectric;
+pub mod diffuselight;
 pub mod lambertian;
 pub mod metal;
 
 pub use material::*;
+pub use bsdfsample::*;
 pub use dielectric::*;
+pub use diffuselight::*;
 pub use lambertian::*;
 pub use metal::*;
@@ -1,9 +1,4 @@
-use super::*;
-
-use crate::random;
-
-use std::fmt;
-use std::fmt::Display;
+use crate::random::Sampler;
 
 use std::ops::Add;
 use std::ops::AddAssign;
@@ -36,12 +31,24 @@ impl Color {
         };
     }
 
-    pub fn random_unit() -> Color {
-        return Color::new(random::double_unit(), random::double_unit(), random::double_unit())
+    pub fn r(&self) -> f64 {
+        return self.r;
+    }
+
+    pub fn g(&self) -> f64 {
+        return self.g;
+    }
+
+    pub fn b(&self) -> f64 {
+        return self.b;
+    }
+
+    pub fn random_unit(sampler: &mut Sampler) -> Color {
+        return Color::new(sampler.double_unit(), sampler.double_unit(), sampler.double_unit())
     }
 
-    pub fn random_range(min: f64, max: f64) -> Color {
-        return Color::new(random::double_range(min, max), random::double_range(min, max), random::double_range(min, max))
+    pub fn random_range(min: f64, max: f64, sampler: &mut Sampler) -> Color {
+        return Color::new(sampler.double_range(min, max), sampler.double_range(min, max), sampler.double_range(min, max))
     }
 }
 
@@ -85,24 +92,6 @@ impl DivAssign<f64> for Color {
     }
 }
 
-impl Display for Color {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut r = self.r;
-        let mut g = self.g;
-        let mut b = self.b;
-
-        r = r.powf(1.0/2.2);
-        g = g.powf(1.0/2.2);
-        b = b.powf(1.0/2.2);
-
-        let ir = (256.0 * clamp(r, 0.0, 0.999)) as i32;
-        let ig = (256.0 * clamp(g, 0.0, 0.999)) as i32;
-        let ib = (256.0 * clamp(b, 0.0, 0.999)) as i32;
-
-        write!(f, "{} {} {}", ir, ig, ib)
-    }
-}
-
 impl Mul for Color {
     type Output = Color;
 
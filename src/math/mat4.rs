@@ -0,0 +1,278 @@
+use super::*;
+
+use std::ops::Mul;
+
+// A 4x4 matrix for affine transforms of points, directions and normals,
+// stored by rows. Points are column vectors with a w of one.
+#[derive(Clone, Copy)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        return Mat4 { m };
+    }
+
+    pub fn identity() -> Mat4 {
+        return Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        return Mat4::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+    }
+
+    pub fn scaling(factors: Vec3) -> Mat4 {
+        return Mat4::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+    }
+
+    // A counterclockwise rotation about the axis, looking down the axis
+    // towards the origin, by the angle in degrees.
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat4 {
+        let a = unit_vector(axis);
+        let (sin, cos) = degrees_to_radians(angle).sin_cos();
+        let t = 1.0 - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+
+        return Mat4::new([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+
+        return Mat4::new(m);
+    }
+
+    // The inverse by Gauss-Jordan elimination with partial pivoting, or None
+    // if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inverse = Mat4::identity().m;
+
+        for column in 0..4 {
+            let pivot = (column..4).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs())).unwrap();
+
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+
+                let factor = a[row][column];
+
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+
+        return Some(Mat4::new(inverse));
+    }
+
+    // The inverse of a matrix without translation, such as the stretch from
+    // decompose, from the cofactors of its upper left 3x3. None if it is
+    // singular.
+    pub fn linear_inverse(&self) -> Option<Mat4> {
+        let m = &self.m;
+
+        let c00 = m[1][1] * m[2][2] - m[1][2] * m[2][1];
+        let c01 = m[1][2] * m[2][0] - m[1][0] * m[2][2];
+        let c02 = m[1][0] * m[2][1] - m[1][1] * m[2][0];
+        let determinant = m[0][0] * c00 + m[0][1] * c01 + m[0][2] * c02;
+
+        if determinant == 0.0 {
+            return None;
+        }
+
+        let c10 = m[0][2] * m[2][1] - m[0][1] * m[2][2];
+        let c11 = m[0][0] * m[2][2] - m[0][2] * m[2][0];
+        let c12 = m[0][1] * m[2][0] - m[0][0] * m[2][1];
+        let c20 = m[0][1] * m[1][2] - m[0][2] * m[1][1];
+        let c21 = m[0][2] * m[1][0] - m[0][0] * m[1][2];
+        let c22 = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+        let d = 1.0 / determinant;
+
+        return Some(Mat4::new([
+            [c00 * d, c10 * d, c20 * d, 0.0],
+            [c01 * d, c11 * d, c21 * d, 0.0],
+            [c02 * d, c12 * d, c22 * d, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        return self.m[row][column];
+    }
+
+    pub fn lerp(&self, other: &Mat4, t: f64) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (1.0 - t) * self.m[i][j] + t * other.m[i][j];
+            }
+        }
+
+        return Mat4::new(m);
+    }
+
+    // Splits an affine transform into a translation, a rotation and a
+    // stretch, so that it equals translation * rotation * stretch. The
+    // rotation is found by polar decomposition, repeatedly averaging the
+    // matrix with its inverse transpose until it stops changing. None if the
+    // matrix is singular.
+    pub fn decompose(&self) -> Option<(Vec3, Mat4, Mat4)> {
+        let translation = Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3]);
+        let linear = Mat4::translation(-translation) * *self;
+        let mut rotation = linear;
+
+        for _ in 0..100 {
+            let next = rotation.lerp(&rotation.inverse()?.transpose(), 0.5);
+            let change = (0..3).flat_map(|i| (0..3).map(move |j| (i, j))).map(|(i, j)| (next.m[i][j] - rotation.m[i][j]).abs()).fold(0.0, f64::max);
+
+            rotation = next;
+
+            if change < 1e-12 {
+                break;
+            }
+        }
+
+        // A reflection isn't a rotation, so it is moved into the stretch.
+        let r = &rotation.m;
+        let determinant = r[0][0] * (r[1][1] * r[2][2] - r[1][2] * r[2][1]) - r[0][1] * (r[1][0] * r[2][2] - r[1][2] * r[2][0]) + r[0][2] * (r[1][0] * r[2][1] - r[1][1] * r[2][0]);
+
+        if determinant < 0.0 {
+            rotation = rotation * Mat4::scaling(Vec3::new(-1.0, -1.0, -1.0));
+        }
+
+        let stretch = rotation.transpose() * linear;
+
+        return Some((translation, rotation, stretch));
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        return self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3]);
+    }
+
+    // Transforms a direction, which isn't affected by translation.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+
+        return Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        );
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+
+        return Mat4::new(m);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max_difference(a: &Mat4, b: &Mat4) -> f64 {
+        return (0..4).flat_map(|i| (0..4).map(move |j| (i, j))).map(|(i, j)| (a.get(i, j) - b.get(i, j)).abs()).fold(0.0, f64::max);
+    }
+
+    fn determinant3(m: &Mat4) -> f64 {
+        return m.get(0, 0) * (m.get(1, 1) * m.get(2, 2) - m.get(1, 2) * m.get(2, 1))
+            - m.get(0, 1) * (m.get(1, 0) * m.get(2, 2) - m.get(1, 2) * m.get(2, 0))
+            + m.get(0, 2) * (m.get(1, 0) * m.get(2, 1) - m.get(1, 1) * m.get(2, 0));
+    }
+
+    #[test]
+    fn inverse() {
+        let m = Mat4::translation(Vec3::new(1.0, -2.0, 3.0)) * Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 40.0) * Mat4::scaling(Vec3::new(2.0, 0.5, -3.0));
+
+        assert!(max_difference(&(m * m.inverse().unwrap()), &Mat4::identity()) < 1e-12);
+        assert!(max_difference(&(m * m.linear_inverse().unwrap()), &Mat4::translation(Vec3::new(1.0, -2.0, 3.0))) < 1e-12);
+
+        let singular = Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 30.0) * Mat4::scaling(Vec3::new(1.0, 0.0, 1.0));
+        assert!(singular.inverse().is_none());
+        assert!(singular.linear_inverse().is_none());
+        assert!(Mat4::new([[0.0; 4]; 4]).inverse().is_none());
+    }
+
+    #[test]
+    fn decompose() {
+        let translation = Vec3::new(1.0, -2.0, 3.0);
+        let rotation = Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 40.0);
+
+        // A stretch along rotated axes, and one with a reflection.
+        let tilt = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 30.0);
+        let stretches = [
+            tilt * Mat4::scaling(Vec3::new(2.0, 0.5, 3.0)) * tilt.transpose(),
+            Mat4::scaling(Vec3::new(-1.0, 2.0, 3.0)),
+        ];
+
+        for stretch in &stretches {
+            let m = Mat4::translation(translation) * rotation * *stretch;
+            let (t, r, s) = m.decompose().unwrap();
+
+            assert!((t - translation).length() < 1e-12);
+            assert!(max_difference(&(Mat4::translation(t) * r * s), &m) < 1e-9);
+            assert!(max_difference(&(r * r.transpose()), &Mat4::identity()) < 1e-9);
+            assert!((determinant3(&r) - 1.0).abs() < 1e-9);
+            assert!(max_difference(&s, &s.transpose()) < 1e-9);
+        }
+
+        assert!(max_difference(&Mat4::translation(translation).decompose().unwrap().1, &Mat4::identity()) < 1e-12);
+        assert!(Mat4::scaling(Vec3::new(1.0, 0.0, 1.0)).decompose().is_none());
+    }
+}
@@ -1,8 +1,14 @@
 pub mod color;
+pub mod mat4;
+pub mod onb;
+pub mod quat;
 pub mod ray;
 pub mod vec3;
 
 pub use color::*;
+pub use mat4::*;
+pub use onb::*;
+pub use quat::*;
 pub use ray::*;
 pub use vec3::*;
 
@@ -12,6 +18,10 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     return x;
 }
 
+pub fn degrees_to_radians(degrees: f64) -> f64 {
+    return degrees * std::f64::consts::PI / 180.0;
+}
+
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     return v - 2.0 * dot(v, n) * n;
 }
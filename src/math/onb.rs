@@ -0,0 +1,24 @@
+use super::*;
+
+// An orthonormal basis, for turning directions sampled about the z axis into
+// directions about an arbitrary axis w.
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new_from_w(w: Vec3) -> Onb {
+        let w = unit_vector(w);
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = unit_vector(cross(w, a));
+        let u = cross(w, v);
+
+        return Onb { u, v, w };
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        return a * self.u + b * self.v + c * self.w;
+    }
+}
@@ -0,0 +1,135 @@
+use super::*;
+
+// A unit quaternion representing a rotation, used to interpolate rotations
+// smoothly between keyframes.
+#[derive(Clone, Copy)]
+pub struct Quat {
+    w: f64,
+    v: Vec3,
+}
+
+impl Quat {
+    pub fn new(w: f64, v: Vec3) -> Quat {
+        return Quat { w, v };
+    }
+
+    // The rotation in the upper left 3x3 of a matrix, which must be a proper
+    // rotation.
+    pub fn from_rotation(m: &Mat4) -> Quat {
+        let trace = m.get(0, 0) + m.get(1, 1) + m.get(2, 2);
+
+        // Divides by the largest of the four components, which can't be
+        // close to zero.
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quat::new(0.25 * s, Vec3::new(m.get(2, 1) - m.get(1, 2), m.get(0, 2) - m.get(2, 0), m.get(1, 0) - m.get(0, 1)) / s)
+        } else if m.get(0, 0) > m.get(1, 1) && m.get(0, 0) > m.get(2, 2) {
+            let s = 2.0 * (1.0 + m.get(0, 0) - m.get(1, 1) - m.get(2, 2)).sqrt();
+            Quat::new((m.get(2, 1) - m.get(1, 2)) / s, Vec3::new(0.25 * s, (m.get(0, 1) + m.get(1, 0)) / s, (m.get(0, 2) + m.get(2, 0)) / s))
+        } else if m.get(1, 1) > m.get(2, 2) {
+            let s = 2.0 * (1.0 + m.get(1, 1) - m.get(0, 0) - m.get(2, 2)).sqrt();
+            Quat::new((m.get(0, 2) - m.get(2, 0)) / s, Vec3::new((m.get(0, 1) + m.get(1, 0)) / s, 0.25 * s, (m.get(1, 2) + m.get(2, 1)) / s))
+        } else {
+            let s = 2.0 * (1.0 + m.get(2, 2) - m.get(0, 0) - m.get(1, 1)).sqrt();
+            Quat::new((m.get(1, 0) - m.get(0, 1)) / s, Vec3::new((m.get(0, 2) + m.get(2, 0)) / s, (m.get(1, 2) + m.get(2, 1)) / s, 0.25 * s))
+        };
+
+        return q.normalized();
+    }
+
+    pub fn dot(&self, other: &Quat) -> f64 {
+        return self.w * other.w + dot(self.v, other.v);
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let length = self.dot(self).sqrt();
+        return Quat::new(self.w / length, self.v / length);
+    }
+
+    pub fn to_rotation(self) -> Mat4 {
+        let (w, x, y, z) = (self.w, self.v.x(), self.v.y(), self.v.z());
+
+        return Mat4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+    }
+
+    // The angle in radians of the smallest rotation that takes this rotation
+    // to the other one.
+    pub fn angle_to(&self, other: &Quat) -> f64 {
+        return 2.0 * self.dot(other).abs().min(1.0).acos();
+    }
+
+    // Spherical linear interpolation, which rotates at a constant rate about
+    // a fixed axis, taking the shorter way around.
+    pub fn slerp(&self, other: &Quat, t: f64) -> Quat {
+        let mut cos_theta = self.dot(other);
+        let mut other = *other;
+
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            other = Quat::new(-other.w, -other.v);
+        }
+
+        // Nearly the same rotation, where the sines below vanish.
+        if cos_theta > 0.9995 {
+            return Quat::new((1.0 - t) * self.w + t * other.w, (1.0 - t) * self.v + t * other.v).normalized();
+        }
+
+        let theta = cos_theta.acos();
+        let a = ((1.0 - t) * theta).sin() / theta.sin();
+        let b = (t * theta).sin() / theta.sin();
+
+        return Quat::new(a * self.w + b * other.w, a * self.v + b * other.v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max_difference(a: &Mat4, b: &Mat4) -> f64 {
+        return (0..4).flat_map(|i| (0..4).map(move |j| (i, j))).map(|(i, j)| (a.get(i, j) - b.get(i, j)).abs()).fold(0.0, f64::max);
+    }
+
+    // Rotations that take each of the four branches of from_rotation: a
+    // positive trace, and half turns about the x, y and z axes, where the
+    // diagonal element of that axis is the largest.
+    #[test]
+    fn rotation_round_trip() {
+        let rotations = [
+            Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 40.0),
+            Mat4::rotation(Vec3::new(1.0, 0.1, 0.2), 170.0),
+            Mat4::rotation(Vec3::new(0.1, 1.0, -0.2), 175.0),
+            Mat4::rotation(Vec3::new(-0.2, 0.1, 1.0), 180.0),
+        ];
+
+        for m in &rotations {
+            assert!(max_difference(&Quat::from_rotation(m).to_rotation(), m) < 1e-12);
+        }
+
+        assert!(max_difference(&Quat::from_rotation(&Mat4::identity()).to_rotation(), &Mat4::identity()) < 1e-12);
+    }
+
+    #[test]
+    fn slerp() {
+        let a = Quat::from_rotation(&Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 20.0));
+        let b = Quat::from_rotation(&Mat4::rotation(Vec3::new(-1.0, 0.5, 0.0), 130.0));
+
+        assert!(a.slerp(&b, 0.0).angle_to(&a) < 1e-6);
+        assert!(a.slerp(&b, 1.0).angle_to(&b) < 1e-6);
+
+        let middle = a.slerp(&b, 0.5);
+        assert!((middle.dot(&middle) - 1.0).abs() < 1e-12);
+        assert!((middle.angle_to(&a) - 0.5 * a.angle_to(&b)).abs() < 1e-9);
+        assert!((middle.angle_to(&b) - 0.5 * a.angle_to(&b)).abs() < 1e-9);
+
+        // The shorter way around, even if the quaternions have opposite
+        // signs.
+        let negated = Quat::new(-b.w, -b.v);
+        assert!(a.slerp(&negated, 0.5).angle_to(&middle) < 1e-6);
+    }
+}
@@ -15,14 +15,6 @@ impl Ray {
         };
     }
 
-    pub fn new_default() -> Ray {
-        return Ray {
-            origin: Vec3::new_default(),
-            direction: Vec3::new_default(),
-            time: 0.0,
-        };
-    }
-
     pub fn origin(&self) -> Vec3  {
         return self.origin;
     }
@@ -10,8 +10,6 @@ use std::ops::Neg;
 use std::ops::Sub;
 use std::ops::SubAssign;
 
-use crate::random;
-
 #[derive(Clone, Copy)]
 pub struct Vec3 {
     x: f64,
@@ -57,18 +55,6 @@ impl Vec3 {
         *self /= self.length();
         return self;
     }
-
-    pub fn random_unit() -> Vec3 {
-        return Vec3::random_range(-1.0, 1.0)
-    }
-
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
-        return Vec3 {
-            x: random::double_range(min, max),
-            y: random::double_range(min, max),
-            z: random::double_range(min, max),
-        };
-    }
 }
 
 impl Add for Vec3 {
@@ -0,0 +1,8 @@
+mod error;
+mod mtl;
+#[allow(clippy::module_inception)]
+mod obj;
+
+pub use error::*;
+pub use mtl::*;
+pub use obj::*;
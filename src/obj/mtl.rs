@@ -0,0 +1,213 @@
+use super::*;
+
+use crate::material::*;
+use crate::math::*;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    specular_exponent: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    illumination_model: i32,
+}
+
+impl MtlMaterial {
+    fn new_default() -> MtlMaterial {
+        return MtlMaterial {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            emission: Color::new(0.0, 0.0, 0.0),
+            specular_exponent: 0.0,
+            refraction_index: 1.5,
+            dissolve: 1.0,
+            illumination_model: 2,
+        };
+    }
+
+    // Emissive materials become DiffuseLight, transparent materials become
+    // Dielectric, materials that are more specular than diffuse become Metal
+    // with a fuzziness derived from the specular exponent, and everything
+    // else becomes Lambertian.
+    fn kind(&self) -> MtlKind {
+        if max_component(self.emission) > 0.0 {
+            return MtlKind::Light;
+        }
+
+        let transparent = self.dissolve < 1.0 || [4, 6, 7, 9].contains(&self.illumination_model);
+
+        if transparent {
+            return MtlKind::Dielectric;
+        }
+
+        if self.illumination_model == 3 || max_component(self.specular) > max_component(self.diffuse) {
+            return MtlKind::Metal((2.0 / (self.specular_exponent.max(0.0) + 2.0)).sqrt());
+        }
+
+        return MtlKind::Lambertian;
+    }
+
+    fn to_material(&self) -> Arc<dyn Material> {
+        return match self.kind() {
+            MtlKind::Light => Arc::new(DiffuseLight::new(self.emission)),
+            MtlKind::Dielectric => Arc::new(Dielectric::new(self.refraction_index)),
+            MtlKind::Metal(fuzzing) => Arc::new(Metal::new(self.specular, fuzzing)),
+            MtlKind::Lambertian => Arc::new(Lambertian::new(self.diffuse)),
+        };
+    }
+}
+
+// The material an MTL material becomes, with the fuzzing of a Metal.
+#[derive(Debug, PartialEq)]
+enum MtlKind {
+    Light,
+    Dielectric,
+    Metal(f64),
+    Lambertian,
+}
+
+fn max_component(c: Color) -> f64 {
+    return c.r().max(c.g()).max(c.b());
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| ObjError::new(path, 0, format!("could not read material library: {}", e)))?;
+    let materials = parse_mtl(&contents, path)?;
+
+    return Ok(materials.iter().map(|(name, material)| (name.clone(), material.to_material())).collect());
+}
+
+// The materials of the contents of a material library, read from path.
+fn parse_mtl(contents: &str, path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: String| ObjError::new(path, line_number, message);
+
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if arguments.is_empty() {
+                return Err(error("newmtl without a material name".to_string()));
+            }
+
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+
+            current = Some((arguments.join(" "), MtlMaterial::new_default()));
+            continue;
+        }
+
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None => return Err(error(format!("'{}' before the first newmtl", keyword))),
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = parse_color(&arguments).map_err(error)?,
+            "Ks" => material.specular = parse_color(&arguments).map_err(error)?,
+            "Ke" => material.emission = parse_color(&arguments).map_err(error)?,
+            "Ns" => material.specular_exponent = parse_single(&arguments).map_err(error)?,
+            "Ni" => material.refraction_index = parse_single(&arguments).map_err(error)?,
+            "d" => material.dissolve = parse_single(&arguments).map_err(error)?,
+            "Tr" => material.dissolve = 1.0 - parse_single(&arguments).map_err(error)?,
+            "illum" => material.illumination_model = parse_single(&arguments).map_err(error)? as i32,
+            // Ambient colors, texture maps and the like have no
+            // counterpart in our materials.
+            _ => {},
+        }
+    }
+
+    if let Some((name, material)) = current.take() {
+        materials.insert(name, material);
+    }
+
+    return Ok(materials);
+}
+
+pub fn parse_number(token: &str) -> Result<f64, String> {
+    return token.parse::<f64>().map_err(|_| format!("invalid number '{}'", token));
+}
+
+fn parse_single(arguments: &[&str]) -> Result<f64, String> {
+    if arguments.len() != 1 {
+        return Err(format!("expected 1 number, found {}", arguments.len()));
+    }
+
+    return parse_number(arguments[0]);
+}
+
+fn parse_color(arguments: &[&str]) -> Result<Color, String> {
+    // A single value means a gray color.
+    return match arguments {
+        [v] => {
+            let v = parse_number(v)?;
+            Ok(Color::new(v, v, v))
+        },
+        [r, g, b] => Ok(Color::new(parse_number(r)?, parse_number(g)?, parse_number(b)?)),
+        _ => Err(format!("expected 1 or 3 color components, found {}", arguments.len())),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(contents: &str) -> HashMap<String, MtlKind> {
+        return match parse_mtl(contents, Path::new("test.mtl")) {
+            Ok(materials) => materials.iter().map(|(name, material)| (name.clone(), material.kind())).collect(),
+            Err(e) => panic!("{}", e),
+        };
+    }
+
+    #[test]
+    fn metal_fuzzing() {
+        let kinds = kinds("newmtl rough\nKd 0.1 0.1 0.1\nKs 0.9 0.9 0.9\nNs 0\nnewmtl polished\nKd 0.1\nKs 0.9\nNs 198\nnewmtl illum\nKd 0.8\nillum 3");
+
+        assert_eq!(kinds["rough"], MtlKind::Metal(1.0));
+        assert_eq!(kinds["polished"], MtlKind::Metal(0.1));
+        assert_eq!(kinds["illum"], MtlKind::Metal(1.0));
+    }
+
+    #[test]
+    fn transparency() {
+        let kinds = kinds("newmtl dissolved\nd 0.5\nnewmtl transmitted\nTr 0.5\nnewmtl opaque\nTr 0\nnewmtl glass\nillum 7\nnewmtl lamp\nKe 1 1 1\nd 0.5");
+
+        assert_eq!(kinds["dissolved"], MtlKind::Dielectric);
+        assert_eq!(kinds["transmitted"], MtlKind::Dielectric);
+        assert_eq!(kinds["opaque"], MtlKind::Lambertian);
+        assert_eq!(kinds["glass"], MtlKind::Dielectric);
+        assert_eq!(kinds["lamp"], MtlKind::Light);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |contents: &str| match parse_mtl(contents, Path::new("test.mtl")) {
+            Ok(_) => panic!("the library was parsed"),
+            Err(e) => e.to_string(),
+        };
+
+        assert_eq!(error("Kd 1 1 1"), "test.mtl:1: 'Kd' before the first newmtl");
+        assert_eq!(error("newmtl a\n\nNs high"), "test.mtl:3: invalid number 'high'");
+    }
+}
@@ -0,0 +1,347 @@
+use super::*;
+
+use crate::geometry::*;
+use crate::material::*;
+use crate::math::*;
+
+use crate::Entity;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// The faces of one group of an OBJ file, with the vertex data they use
+// copied out of the file's global vertex lists.
+struct Group {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    position_indices: HashMap<usize, u32>,
+    normal_indices: HashMap<usize, u32>,
+    uv_indices: HashMap<usize, u32>,
+    faces: Vec<MeshFace>,
+    material_names: Vec<String>,
+    materials: Vec<Arc<dyn Material>>,
+}
+
+impl Group {
+    fn new() -> Group {
+        return Group {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            position_indices: HashMap::new(),
+            normal_indices: HashMap::new(),
+            uv_indices: HashMap::new(),
+            faces: Vec::new(),
+            material_names: Vec::new(),
+            materials: Vec::new(),
+        };
+    }
+
+    fn material_id(&mut self, name: &str, material: &Arc<dyn Material>) -> u32 {
+        if let Some(i) = self.material_names.iter().position(|n| n == name) {
+            return i as u32;
+        }
+
+        self.material_names.push(name.to_string());
+        self.materials.push(material.clone());
+
+        return (self.materials.len() - 1) as u32;
+    }
+
+    fn into_entity(self) -> Result<Option<Entity>, String> {
+        if self.faces.is_empty() {
+            return Ok(None);
+        }
+
+        let mesh = TriangleMesh::new(self.positions, self.normals, self.uvs, self.faces)?;
+
+        return Ok(Some(Entity::new_with_materials(Box::new(mesh), self.materials)));
+    }
+}
+
+fn local_index<T: Copy>(global_index: usize, global: &[T], local: &mut Vec<T>, indices: &mut HashMap<usize, u32>) -> u32 {
+    return *indices.entry(global_index).or_insert_with(|| {
+        local.push(global[global_index]);
+        return (local.len() - 1) as u32;
+    });
+}
+
+// Resolves a 1-based OBJ index, or a negative index counting back from the
+// last element defined so far, to a 0-based index.
+fn parse_index(token: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = token.parse::<i64>().map_err(|_| format!("invalid {} index '{}'", kind, token))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} out of range, {} defined so far", kind, index, count));
+    }
+
+    return Ok(resolved as usize);
+}
+
+fn parse_vec3(arguments: &[&str]) -> Result<Vec3, String> {
+    if arguments.len() < 3 {
+        return Err(format!("expected 3 coordinates, found {}", arguments.len()));
+    }
+
+    return Ok(Vec3::new(parse_number(arguments[0])?, parse_number(arguments[1])?, parse_number(arguments[2])?));
+}
+
+// Loads a Wavefront OBJ file, together with the material libraries it refers
+// to, as one entity per group. Faces with more than three vertices are split
+// into triangle fans.
+pub fn load_obj(path: &Path) -> Result<Vec<Entity>, ObjError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| ObjError::new(path, 0, format!("could not read file: {}", e)))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let default_material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+    let mut library: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut current_material = (String::new(), default_material);
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+
+    let mut entities = Vec::new();
+    let mut group = Group::new();
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: String| ObjError::new(path, line_number, message);
+
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&arguments).map_err(error)?),
+            "vn" => normals.push(parse_vec3(&arguments).map_err(error)?),
+            "vt" => {
+                if arguments.is_empty() {
+                    return Err(error("expected texture coordinates".to_string()));
+                }
+
+                let u = parse_number(arguments[0]).map_err(error)?;
+                let v = if arguments.len() > 1 { parse_number(arguments[1]).map_err(error)? } else { 0.0 };
+                uvs.push((u, v));
+            },
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!("face with {} vertices, at least 3 are needed", arguments.len())));
+                }
+
+                let mut vertices = Vec::with_capacity(arguments.len());
+
+                for argument in &arguments {
+                    let mut parts = argument.split('/');
+                    let position = parse_index(parts.next().unwrap_or(""), positions.len(), "vertex").map_err(error)?;
+
+                    let uv = match parts.next() {
+                        Some("") | None => None,
+                        Some(token) => Some(parse_index(token, uvs.len(), "texture coordinate").map_err(error)?),
+                    };
+
+                    let normal = match parts.next() {
+                        Some("") | None => None,
+                        Some(token) => Some(parse_index(token, normals.len(), "normal").map_err(error)?),
+                    };
+
+                    if parts.next().is_some() {
+                        return Err(error(format!("invalid face vertex '{}'", argument)));
+                    }
+
+                    vertices.push((
+                        local_index(position, &positions, &mut group.positions, &mut group.position_indices),
+                        uv.map(|i| local_index(i, &uvs, &mut group.uvs, &mut group.uv_indices)),
+                        normal.map(|i| local_index(i, &normals, &mut group.normals, &mut group.normal_indices)),
+                    ));
+                }
+
+                let material_id = group.material_id(&current_material.0, &current_material.1);
+
+                for i in 1..vertices.len() - 1 {
+                    let corners = [vertices[0], vertices[i], vertices[i + 1]];
+                    let face_positions = [corners[0].0, corners[1].0, corners[2].0];
+
+                    // Vertex normals and texture coordinates are only used if
+                    // all corners of the face have them.
+                    let face_uvs = match (corners[0].1, corners[1].1, corners[2].1) {
+                        (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                        _ => None,
+                    };
+
+                    let face_normals = match (corners[0].2, corners[1].2, corners[2].2) {
+                        (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                        _ => None,
+                    };
+
+                    group.faces.push(MeshFace::new(face_positions, face_normals, face_uvs, material_id));
+                }
+            },
+            "g" | "o" => {
+                let finished = std::mem::replace(&mut group, Group::new());
+                entities.extend(finished.into_entity().map_err(error)?);
+            },
+            "mtllib" => {
+                if arguments.is_empty() {
+                    return Err(error("mtllib without a file name".to_string()));
+                }
+
+                for file_name in &arguments {
+                    library.extend(load_mtl(&directory.join(file_name))?);
+                }
+            },
+            "usemtl" => {
+                let name = arguments.join(" ");
+
+                match library.get(&name) {
+                    Some(material) => current_material = (name, material.clone()),
+                    None => return Err(error(format!("unknown material '{}'", name))),
+                }
+            },
+            // Smoothing groups, lines, points and free-form geometry are not
+            // supported and are skipped.
+            _ => {},
+        }
+    }
+
+    entities.extend(group.into_entity().map_err(|e| ObjError::new(path, 0, e))?);
+
+    return Ok(entities);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::random::Sampler;
+
+    use std::path::PathBuf;
+
+    // Writes the files to a directory of their own and returns the path of
+    // the first one.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("raytracer-obj-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for (name, contents) in files {
+            std::fs::write(directory.join(name), contents).unwrap();
+        }
+
+        return directory.join(files[0].0);
+    }
+
+    fn load(test: &str, files: &[(&str, &str)]) -> Vec<Entity> {
+        return match load_obj(&write_files(test, files)) {
+            Ok(entities) => entities,
+            Err(e) => panic!("{}", e),
+        };
+    }
+
+    fn load_error(test: &str, files: &[(&str, &str)]) -> String {
+        return match load_obj(&write_files(test, files)) {
+            Ok(_) => panic!("the file loaded"),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    fn hit_from_above(entity: &Entity, x: f64, y: f64) -> Option<HitRecord> {
+        let mut rec = HitRecord::new_default();
+        let r = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        if entity.geometry.intersect(&r, 0.001, f64::INFINITY, &mut rec) {
+            return Some(rec);
+        }
+
+        return None;
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices() {
+        let entities = load("negative", &[("a.obj", "v 5 5 5\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n")]);
+
+        assert_eq!(entities.len(), 1);
+        assert!((entities[0].geometry.area(0) - 0.5).abs() < 1e-12);
+        assert!(hit_from_above(&entities[0], 0.25, 0.25).is_some());
+    }
+
+    #[test]
+    fn face_vertex_formats() {
+        let contents = format!("{}vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1 4/4/1\n", SQUARE);
+        let entities = load("formats-uv", &[("a.obj", &contents)]);
+
+        // The quad is split into two triangles covering all of it.
+        assert!((entities[0].geometry.area(0) - 1.0).abs() < 1e-12);
+
+        for &(x, y) in &[(0.75, 0.25), (0.25, 0.75)] {
+            let rec = hit_from_above(&entities[0], x, y).unwrap();
+            assert!((rec.uv().0 - x).abs() < 1e-12 && (rec.uv().1 - y).abs() < 1e-12);
+        }
+
+        // A normal tilted away from the face normal shows up as the shading
+        // normal.
+        let contents = format!("{}vn 0 0.6 0.8\nf 1//1 2//1 3//1\n", SQUARE);
+        let entities = load("formats-normal", &[("a.obj", &contents)]);
+        let rec = hit_from_above(&entities[0], 0.75, 0.25).unwrap();
+
+        assert!((rec.normal().y() - 0.6).abs() < 1e-12 && (rec.normal().z() - 0.8).abs() < 1e-12);
+        assert!((rec.geometric_normal().z() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn entity_per_group() {
+        let contents = format!("{}f 1 2 3\ng first\nf 1 3 4\no second\nf 1 2 3\nf 1 3 4\ng empty\n", SQUARE);
+        let entities = load("groups", &[("a.obj", &contents)]);
+
+        assert_eq!(entities.len(), 3);
+        assert!((entities[0].geometry.area(0) - 0.5).abs() < 1e-12);
+        assert!((entities[1].geometry.area(0) - 0.5).abs() < 1e-12);
+        assert!((entities[2].geometry.area(0) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn materials_by_face() {
+        let contents = format!("mtllib a.mtl\n{}usemtl lamp\nf 1 2 3\nusemtl wall\nf 1 3 4\n", SQUARE);
+        let entities = load("materials", &[("a.obj", &contents), ("a.mtl", "newmtl wall\nKd 0.5 0.5 0.5\nnewmtl lamp\nKe 4 4 4\n")]);
+
+        assert_eq!(entities[0].materials.len(), 2);
+        assert!(entities[0].materials[0].is_emissive());
+        assert!(!entities[0].materials[1].is_emissive());
+        assert_eq!(hit_from_above(&entities[0], 0.75, 0.25).unwrap().material_id(), 0);
+        assert_eq!(hit_from_above(&entities[0], 0.25, 0.75).unwrap().material_id(), 1);
+        assert!(entities[0].geometry.sample_surface(1, 0.0, &mut Sampler::new(1)).is_some());
+    }
+
+    #[test]
+    fn error_lines() {
+        let contents = format!("{}\nf 1 2 5\n", SQUARE);
+        let message = load_error("bad-index", &[("a.obj", &contents)]);
+        assert!(message.ends_with("a.obj:6: vertex index 5 out of range, 4 defined so far"), "{}", message);
+
+        let message = load_error("bad-negative-index", &[("a.obj", "v 0 0 0\nf -1 -2 -3\n")]);
+        assert!(message.ends_with("a.obj:2: vertex index -2 out of range, 1 defined so far"), "{}", message);
+
+        let contents = format!("mtllib a.mtl\n{}usemtl stone\nf 1 2 3\n", SQUARE);
+        let message = load_error("unknown-material", &[("a.obj", &contents), ("a.mtl", "newmtl wall\nKd 0.5\n")]);
+        assert!(message.ends_with("a.obj:6: unknown material 'stone'"), "{}", message);
+    }
+}
@@ -0,0 +1,65 @@
+// A small, fast pseudo random number generator (xoshiro256**) that is passed
+// explicitly to everything that needs random numbers. Seeding it the same way
+// always produces the same sequence.
+#[derive(Clone)]
+pub struct Sampler {
+    state: [u64; 4],
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        let mut splitmix_state = seed;
+
+        return Sampler {
+            state: [
+                splitmix64(&mut splitmix_state),
+                splitmix64(&mut splitmix_state),
+                splitmix64(&mut splitmix_state),
+                splitmix64(&mut splitmix_state),
+            ],
+        };
+    }
+
+    // A sampler for the pixel at (x, y), independent of the order in which
+    // pixels are rendered.
+    pub fn new_for_pixel(seed: u64, x: i32, y: i32) -> Sampler {
+        let mut hash = seed;
+        let _ = splitmix64(&mut hash);
+        hash ^= ((x as u32 as u64) << 32) | (y as u32 as u64);
+
+        return Sampler::new(splitmix64(&mut hash));
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        return result;
+    }
+
+    // A uniformly distributed number in [0, 1).
+    pub fn double_unit(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 * (1.0 / ((1u64 << 53) as f64));
+    }
+
+    pub fn double_range(&mut self, min: f64, max: f64) -> f64 {
+        return min + (max - min) * self.double_unit();
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    return z ^ (z >> 31);
+}
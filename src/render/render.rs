@@ -0,0 +1,201 @@
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::math::*;
+use crate::random::Sampler;
+
+use crate::ray_color;
+use crate::World;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+const TILE_SIZE: i32 = 32;
+
+pub struct RenderSettings {
+    image_width: i32,
+    image_height: i32,
+    samples_per_pixel: i32,
+    max_depth: i32,
+    seed: u64,
+}
+
+impl RenderSettings {
+    pub fn new(image_width: i32, image_height: i32, samples_per_pixel: i32, max_depth: i32, seed: u64) -> RenderSettings {
+        return RenderSettings {
+            image_width,
+            image_height,
+            samples_per_pixel,
+            max_depth,
+            seed,
+        };
+    }
+
+    pub fn image_width(&self) -> i32 {
+        return self.image_width;
+    }
+
+    pub fn image_height(&self) -> i32 {
+        return self.image_height;
+    }
+
+    pub fn samples_per_pixel(&self) -> i32 {
+        return self.samples_per_pixel;
+    }
+
+    pub fn max_depth(&self) -> i32 {
+        return self.max_depth;
+    }
+
+    pub fn seed(&self) -> u64 {
+        return self.seed;
+    }
+}
+
+// A rectangle of pixels, in framebuffer coordinates where row 0 is the top of
+// the image.
+struct Tile {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+fn make_tiles(settings: &RenderSettings) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+
+    for y in (0..settings.image_height).step_by(TILE_SIZE as usize) {
+        for x in (0..settings.image_width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(settings.image_width - x),
+                height: TILE_SIZE.min(settings.image_height - y),
+            });
+        }
+    }
+
+    return tiles;
+}
+
+fn render_tile(world: &World, cam: &Camera, settings: &RenderSettings, tile: &Tile) -> Vec<Color> {
+    let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+
+    for row in tile.y..tile.y + tile.height {
+        let j = settings.image_height - 1 - row;
+
+        for i in tile.x..tile.x + tile.width {
+            // Every pixel gets its own sequence of random numbers, so the
+            // image does not depend on which thread rendered which tile.
+            let mut sampler = Sampler::new_for_pixel(settings.seed, i, j);
+            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+            for _ in 0..settings.samples_per_pixel {
+                // An image one pixel wide or high spans the view once, instead
+                // of dividing by zero.
+                let u = ((i as f64) + sampler.double_unit()) / ((settings.image_width-1).max(1) as f64);
+                let v = ((j as f64) + sampler.double_unit()) / ((settings.image_height-1).max(1) as f64);
+
+                pixel_color += ray_color(&cam.get_ray(u, v, &mut sampler), world, settings.max_depth, &mut sampler);
+            }
+
+            pixel_color /= settings.samples_per_pixel as f64;
+            pixels.push(pixel_color);
+        }
+    }
+
+    return pixels;
+}
+
+// Renders the image on thread_count worker threads, which take tiles from a
+// shared queue until all are done.
+pub fn render(world: &World, cam: &Camera, settings: &RenderSettings, thread_count: usize) -> Image {
+    let tiles = make_tiles(settings);
+    let next_tile = AtomicUsize::new(0);
+    let mut framebuffer = Image::new(settings.image_width as usize, settings.image_height as usize);
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..thread_count.max(1) {
+            let sender = sender.clone();
+            let tiles = &tiles;
+            let next_tile = &next_tile;
+
+            scope.spawn(move || {
+                loop {
+                    let tile_index = next_tile.fetch_add(1, Ordering::Relaxed);
+
+                    if tile_index >= tiles.len() {
+                        break;
+                    }
+
+                    let pixels = render_tile(world, cam, settings, &tiles[tile_index]);
+
+                    if sender.send((tile_index, pixels)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        drop(sender);
+
+        let mut tiles_remaining = tiles.len();
+
+        for (tile_index, pixels) in receiver {
+            let tile = &tiles[tile_index];
+
+            for row in 0..tile.height {
+                let start = tile.x as usize;
+                let source = (row * tile.width) as usize;
+                framebuffer.row_mut((tile.y + row) as usize)[start..start + tile.width as usize].copy_from_slice(&pixels[source..source + tile.width as usize]);
+            }
+
+            tiles_remaining -= 1;
+            eprint!("\rTiles remaining: {} ", tiles_remaining);
+        }
+    });
+
+    return framebuffer;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::load_scene;
+
+    use std::path::Path;
+
+    fn render_example(width: i32, height: i32, seed: u64, thread_count: usize) -> Image {
+        let mut scene = load_scene(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/example.json")).unwrap();
+        scene.set_settings(RenderSettings::new(width, height, 2, 6, seed));
+        scene.set_aspect_ratio(width as f64 / height as f64);
+
+        return render(scene.world(), scene.camera(), scene.settings(), thread_count);
+    }
+
+    fn same_pixels(a: &Image, b: &Image) -> bool {
+        return a.pixels().iter().zip(b.pixels()).all(|(a, b)| a.r().to_bits() == b.r().to_bits() && a.g().to_bits() == b.g().to_bits() && a.b().to_bits() == b.b().to_bits());
+    }
+
+    #[test]
+    fn same_seed_same_image() {
+        let image = render_example(72, 40, 7, 1);
+
+        assert!(same_pixels(&image, &render_example(72, 40, 7, 1)));
+        assert!(same_pixels(&image, &render_example(72, 40, 7, 4)));
+        assert!(!same_pixels(&image, &render_example(72, 40, 8, 1)));
+    }
+
+    #[test]
+    fn single_pixel_rows_and_columns() {
+        for (width, height) in [(1, 1), (1, 8), (8, 1)] {
+            let image = render_example(width, height, 7, 1);
+
+            assert_eq!(image.pixels().len(), (width * height) as usize);
+            assert!(image.pixels().iter().all(|p| p.r().is_finite() && p.g().is_finite() && p.b().is_finite()));
+        }
+    }
+}
@@ -0,0 +1,3 @@
+use crate::error::FileError;
+
+pub type SceneError = FileError;
@@ -0,0 +1,437 @@
+// A minimal JSON parser that remembers the line each value started on, so
+// that errors found while interpreting a document can point at the input.
+
+// Arrays and objects are parsed recursively, so their nesting is limited to
+// keep a malicious document from overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+pub enum JsonKind {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+pub struct JsonValue {
+    kind: JsonKind,
+    line: usize,
+}
+
+impl JsonValue {
+    pub fn kind(&self) -> &JsonKind {
+        return &self.kind;
+    }
+
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        return match self.kind {
+            JsonKind::Null => "null",
+            JsonKind::Bool(true) => "true",
+            JsonKind::Bool(false) => "false",
+            JsonKind::Number(_) => "a number",
+            JsonKind::String(_) => "a string",
+            JsonKind::Array(_) => "an array",
+            JsonKind::Object(_) => "an object",
+        };
+    }
+}
+
+// A parse error, with the line it was found on.
+pub struct JsonError {
+    pub line: usize,
+    pub message: String,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    depth: usize,
+}
+
+pub fn parse_json(text: &str) -> Result<JsonValue, JsonError> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+        line: 1,
+        depth: 0,
+    };
+
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+
+    if let Some(&c) = parser.chars.peek() {
+        return Err(parser.error(format!("unexpected '{}' after the end of the document", c)));
+    }
+
+    return Ok(value);
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: String) -> JsonError {
+        return JsonError { line: self.line, message };
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+
+        if c == Some('\n') {
+            self.line += 1;
+        }
+
+        return c;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        return match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found the end of the document", expected))),
+        };
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, JsonError> {
+        self.skip_whitespace();
+        let line = self.line;
+
+        let kind = match self.chars.peek() {
+            Some(&c) if c == '{' || c == '[' => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(format!("arrays and objects are nested more than {} deep", MAX_DEPTH)));
+                }
+
+                self.depth += 1;
+                let kind = if c == '{' { self.parse_object()? } else { self.parse_array()? };
+                self.depth -= 1;
+
+                kind
+            },
+            Some('"') => JsonKind::String(self.parse_string()?),
+            Some('t') => self.parse_keyword("true", JsonKind::Bool(true))?,
+            Some('f') => self.parse_keyword("false", JsonKind::Bool(false))?,
+            Some('n') => self.parse_keyword("null", JsonKind::Null)?,
+            Some(&c) if c == '-' || c.is_ascii_digit() => JsonKind::Number(self.parse_number()?),
+            Some(&c) => return Err(self.error(format!("unexpected '{}'", c))),
+            None => return Err(self.error("unexpected end of the document".to_string())),
+        };
+
+        return Ok(JsonValue { kind, line });
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, kind: JsonKind) -> Result<JsonKind, JsonError> {
+        for expected in keyword.chars() {
+            if self.chars.peek() != Some(&expected) {
+                return Err(self.error(format!("invalid literal, expected '{}'", keyword)));
+            }
+
+            self.next();
+        }
+
+        return Ok(kind);
+    }
+
+    fn parse_number(&mut self) -> Result<f64, JsonError> {
+        let mut text = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+
+            text.push(c);
+            self.next();
+        }
+
+        if !is_json_number(&text) {
+            return Err(self.error(format!("invalid number '{}'", text)));
+        }
+
+        return match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(self.error(format!("number '{}' is out of range", text))),
+        };
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            let digit = self.next().and_then(|c| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| self.error("invalid \\u escape".to_string()))?;
+        }
+
+        return Ok(code);
+    }
+
+    // The character of a \u escape, whose four hex digits come next.
+    // Characters outside the basic multilingual plane are written as a pair
+    // of escaped UTF-16 surrogates.
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let code = self.parse_hex4()?;
+
+        if (0xdc00..0xe000).contains(&code) {
+            return Err(self.error("unpaired surrogate in a \\u escape".to_string()));
+        }
+
+        if !(0xd800..0xdc00).contains(&code) {
+            return Ok(std::char::from_u32(code).unwrap());
+        }
+
+        if self.next() != Some('\\') || self.next() != Some('u') {
+            return Err(self.error("unpaired surrogate in a \\u escape".to_string()));
+        }
+
+        let low = self.parse_hex4()?;
+
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error("unpaired surrogate in a \\u escape".to_string()));
+        }
+
+        return Ok(std::char::from_u32(0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)).unwrap());
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("invalid escape sequence".to_string())),
+                    };
+
+                    text.push(c);
+                },
+                Some('\n') => return Err(self.error("unterminated string".to_string())),
+                Some(c) => text.push(c),
+                None => return Err(self.error("unterminated string".to_string())),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonKind, JsonError> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+
+        if self.chars.peek() == Some(&']') {
+            self.next();
+            return Ok(JsonKind::Array(elements));
+        }
+
+        loop {
+            elements.push(self.parse_value()?);
+            self.skip_whitespace();
+
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonKind::Array(elements)),
+                Some(c) => return Err(self.error(format!("expected ',' or ']', found '{}'", c))),
+                None => return Err(self.error("unterminated array".to_string())),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonKind, JsonError> {
+        self.expect('{')?;
+        let mut members: Vec<(String, JsonValue)> = Vec::new();
+        self.skip_whitespace();
+
+        if self.chars.peek() == Some(&'}') {
+            self.next();
+            return Ok(JsonKind::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+
+            if self.chars.peek() != Some(&'"') {
+                return Err(self.error("expected a quoted member name".to_string()));
+            }
+
+            let key = self.parse_string()?;
+
+            if members.iter().any(|(k, _)| *k == key) {
+                return Err(self.error(format!("duplicate member '{}'", key)));
+            }
+
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.push((key, self.parse_value()?));
+            self.skip_whitespace();
+
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonKind::Object(members)),
+                Some(c) => return Err(self.error(format!("expected ',' or '}}', found '{}'", c))),
+                None => return Err(self.error("unterminated object".to_string())),
+            }
+        }
+    }
+}
+
+// Whether text follows the JSON number grammar, which is stricter than
+// Rust's: no leading zeros, and digits on both sides of the decimal point.
+fn is_json_number(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+
+    let digits = |i: &mut usize| {
+        let start = *i;
+
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            *i += 1;
+        }
+
+        return *i - start;
+    };
+
+    if i < bytes.len() && bytes[i] == b'-' {
+        i += 1;
+    }
+
+    let integer_start = i;
+    let integer_digits = digits(&mut i);
+
+    if integer_digits == 0 || (integer_digits > 1 && bytes[integer_start] == b'0') {
+        return false;
+    }
+
+    if i < bytes.len() && bytes[i] == b'.' {
+        i += 1;
+
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+        i += 1;
+
+        if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+            i += 1;
+        }
+
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+
+    return i == bytes.len();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_string(text: &str) -> Result<String, String> {
+        return match parse_json(text) {
+            Ok(JsonValue { kind: JsonKind::String(s), .. }) => Ok(s),
+            Ok(value) => Err(format!("parsed {}", value.type_name())),
+            Err(e) => Err(e.message),
+        };
+    }
+
+    fn parse_number(text: &str) -> Option<f64> {
+        return match parse_json(text) {
+            Ok(JsonValue { kind: JsonKind::Number(n), .. }) => Some(n),
+            _ => None,
+        };
+    }
+
+    fn error_line(text: &str) -> usize {
+        return match parse_json(text) {
+            Ok(_) => panic!("parsed '{}'", text),
+            Err(e) => e.line,
+        };
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(parse_string(r#""a\"b\\c\/d\b\f\n\r\t""#).unwrap(), "a\"b\\c/d\u{8}\u{c}\n\r\t");
+        assert_eq!(parse_string(r#""\u00e9\u4E2D""#).unwrap(), "\u{e9}\u{4e2d}");
+        assert_eq!(parse_string(r#""\ud83d\ude00!""#).unwrap(), "\u{1f600}!");
+
+        assert!(parse_string(r#""\ud83d""#).is_err());
+        assert!(parse_string(r#""\ud83dx""#).is_err());
+        assert!(parse_string(r#""\ude00""#).is_err());
+        assert!(parse_string(r#""\ud83dA""#).is_err());
+        assert!(parse_string(r#""\u12g4""#).is_err());
+        assert!(parse_string(r#""\q""#).is_err());
+        assert!(parse_string("\"open").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        for &(text, value) in &[("0", 0.0), ("-0.5", -0.5), ("12.25", 12.25), ("1e3", 1000.0), ("-2.5E-2", -0.025), ("7e+1", 70.0)] {
+            assert_eq!(parse_number(text), Some(value), "{}", text);
+        }
+
+        for text in ["01", "-", "1.", ".5", "-.5", "1e", "1e+", "+1", "1-2", "--1", "1.2.3", "1e400"] {
+            assert_eq!(parse_number(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn structure() {
+        let members = match parse_json("{\n\"a\": [1, true, null],\n\"b\": {}\n}") {
+            Ok(JsonValue { kind: JsonKind::Object(members), .. }) => members,
+            _ => panic!(),
+        };
+
+        assert_eq!(members.len(), 2);
+        assert_eq!((members[0].0.as_str(), members[0].1.line()), ("a", 2));
+        assert_eq!((members[1].0.as_str(), members[1].1.line()), ("b", 3));
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        assert_eq!(error_line("{}\n\n}"), 3);
+        assert!(parse_json("[1] 2").is_err());
+        assert!(parse_json("[1, ]").is_err());
+        assert!(parse_json("{\"a\": 1,}").is_err());
+    }
+
+    #[test]
+    fn error_lines() {
+        assert_eq!(error_line("{\n\"a\": 1,\n\"a\": 2\n}"), 3);
+        assert_eq!(error_line("[\n1,\n2\n3]"), 4);
+        assert_eq!(error_line("{\n\"a\": tru\n}"), 2);
+        assert_eq!(error_line("[\n\n"), 3);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 200_000;
+        let text = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(parse_json(&text).is_err());
+
+        let text = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse_json(&text).is_ok());
+    }
+}
@@ -0,0 +1,788 @@
+use super::*;
+
+use crate::background::*;
+use crate::camera::Camera;
+use crate::geometry::*;
+use crate::image;
+use crate::material::*;
+use crate::math::*;
+use crate::obj;
+use crate::render::RenderSettings;
+use crate::texture::*;
+
+use crate::Entity;
+use crate::World;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+type Members = [(String, JsonValue)];
+type Textures = HashMap<String, Arc<dyn Texture>>;
+type Objects = HashMap<String, Object>;
+
+// A named object: geometry shared by all instances of the object, with all
+// of its parts grouped, and the materials it uses unless an instance
+// replaces them.
+struct Object {
+    geometry: Arc<dyn Geometry>,
+    materials: Vec<Arc<dyn Material>>,
+}
+
+// Interprets a parsed scene file. Every error refers to the line of the value
+// it was found in.
+struct Loader<'a> {
+    path: &'a Path,
+}
+
+// Loads a JSON scene file. See scenes/example.json for the format.
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let text = std::fs::read_to_string(path).map_err(|e| SceneError::new(path, 0, format!("could not read file: {}", e)))?;
+    return parse_scene(&text, path);
+}
+
+// Loads a scene from the text of a scene file. Files it refers to are
+// relative to path.
+fn parse_scene(text: &str, path: &Path) -> Result<Scene, SceneError> {
+    let document = parse_json(text).map_err(|e| SceneError::new(path, e.line, e.message))?;
+
+    let loader = Loader { path };
+    return loader.load(&document);
+}
+
+impl<'a> Loader<'a> {
+    fn error(&self, value: &JsonValue, message: String) -> SceneError {
+        return SceneError::new(self.path, value.line(), message);
+    }
+
+    fn members<'v>(&self, value: &'v JsonValue, what: &str) -> Result<&'v Members, SceneError> {
+        return match value.kind() {
+            JsonKind::Object(members) => Ok(members),
+            _ => Err(self.error(value, format!("{} must be an object, found {}", what, value.type_name()))),
+        };
+    }
+
+    // The members of an object that may only have the given fields.
+    fn object<'v>(&self, value: &'v JsonValue, what: &str, allowed: &[&str]) -> Result<&'v Members, SceneError> {
+        let members = self.members(value, what)?;
+
+        for (key, member) in members {
+            if !allowed.contains(&key.as_str()) {
+                return Err(self.error(member, format!("unknown field '{}' in {}, expected one of: {}", key, what, allowed.join(", "))));
+            }
+        }
+
+        return Ok(members);
+    }
+
+    fn array<'v>(&self, value: &'v JsonValue, what: &str) -> Result<&'v [JsonValue], SceneError> {
+        return match value.kind() {
+            JsonKind::Array(elements) => Ok(elements),
+            _ => Err(self.error(value, format!("{} must be an array, found {}", what, value.type_name()))),
+        };
+    }
+
+    fn required<'v>(&self, parent: &JsonValue, members: &'v Members, key: &str) -> Result<&'v JsonValue, SceneError> {
+        return match member(members, key) {
+            Some(value) => Ok(value),
+            None => Err(self.error(parent, format!("missing field '{}'", key))),
+        };
+    }
+
+    fn number(&self, value: &JsonValue, what: &str) -> Result<f64, SceneError> {
+        return match value.kind() {
+            JsonKind::Number(n) => Ok(*n),
+            _ => Err(self.error(value, format!("{} must be a number, found {}", what, value.type_name()))),
+        };
+    }
+
+    fn integer(&self, value: &JsonValue, what: &str, min: f64, max: f64) -> Result<f64, SceneError> {
+        let n = self.number(value, what)?;
+
+        if n < min || n > max || n.fract() != 0.0 {
+            return Err(self.error(value, format!("{} must be an integer from {} to {}, found {}", what, min, max, n)));
+        }
+
+        return Ok(n);
+    }
+
+    fn optional_positive_integer(&self, members: &Members, key: &str, default: i32) -> Result<i32, SceneError> {
+        return match member(members, key) {
+            Some(value) => Ok(self.integer(value, key, 1.0, i32::MAX as f64)? as i32),
+            None => Ok(default),
+        };
+    }
+
+    fn string<'v>(&self, value: &'v JsonValue, what: &str) -> Result<&'v str, SceneError> {
+        return match value.kind() {
+            JsonKind::String(s) => Ok(s),
+            _ => Err(self.error(value, format!("{} must be a string, found {}", what, value.type_name()))),
+        };
+    }
+
+    fn triple(&self, value: &JsonValue, what: &str) -> Result<(f64, f64, f64), SceneError> {
+        let elements = self.array(value, what)?;
+
+        if elements.len() != 3 {
+            return Err(self.error(value, format!("{} must have 3 elements, found {}", what, elements.len())));
+        }
+
+        return Ok((self.number(&elements[0], what)?, self.number(&elements[1], what)?, self.number(&elements[2], what)?));
+    }
+
+    fn vec3(&self, value: &JsonValue, what: &str) -> Result<Vec3, SceneError> {
+        let (x, y, z) = self.triple(value, what)?;
+        return Ok(Vec3::new(x, y, z));
+    }
+
+    fn color(&self, value: &JsonValue, what: &str) -> Result<Color, SceneError> {
+        let (r, g, b) = self.triple(value, what)?;
+        return Ok(Color::new(r, g, b));
+    }
+
+    fn optional_number(&self, members: &Members, key: &str, default: f64) -> Result<f64, SceneError> {
+        return match member(members, key) {
+            Some(value) => self.number(value, key),
+            None => Ok(default),
+        };
+    }
+
+    // Files are looked up relative to the scene file.
+    fn file_path(&self, file: &str) -> PathBuf {
+        return self.path.parent().unwrap_or_else(|| Path::new("")).join(file);
+    }
+
+    fn load(&self, document: &JsonValue) -> Result<Scene, SceneError> {
+        let members = self.object(document, "the scene", &["camera", "render", "background", "textures", "materials", "objects", "entities"])?;
+
+        let (camera, aspect_ratio) = self.load_camera(self.required(document, members, "camera")?)?;
+
+        let settings = self.load_settings(member(members, "render"), aspect_ratio)?;
+
+        // Textures may refer to the ones defined before them.
+        let mut textures = HashMap::new();
+
+        if let Some(value) = member(members, "textures") {
+            for (name, texture) in self.members(value, "textures")? {
+                let texture = self.load_texture(texture, &textures)?;
+                textures.insert(name.clone(), texture);
+            }
+        }
+
+        let mut materials = HashMap::new();
+
+        if let Some(value) = member(members, "materials") {
+            for (name, material) in self.members(value, "materials")? {
+                materials.insert(name.clone(), self.load_material(material, &textures)?);
+            }
+        }
+
+        // Objects are defined like an entity or a list of entities, but only
+        // appear in the world through instances, which may also refer to
+        // the objects defined before them. The parts of an object are
+        // grouped, so that every instance needs only one transform.
+        let mut objects = HashMap::new();
+
+        if let Some(value) = member(members, "objects") {
+            for (name, object) in self.members(value, "objects")? {
+                let definitions = match object.kind() {
+                    JsonKind::Array(elements) => elements.as_slice(),
+                    _ => std::slice::from_ref(object),
+                };
+
+                let mut parts: Vec<(Arc<dyn Geometry>, usize)> = Vec::new();
+                let mut part_materials = Vec::new();
+
+                for definition in definitions {
+                    for entity in self.load_entity(definition, &materials, &objects)? {
+                        parts.push((Arc::from(entity.geometry), entity.materials.len()));
+                        part_materials.extend(entity.materials);
+                    }
+                }
+
+                let geometry = match parts.len() {
+                    0 => return Err(self.error(object, format!("object '{}' has no entities", name))),
+                    1 => parts.pop().unwrap().0,
+                    _ => Arc::new(Group::new(parts, camera.time0(), camera.time1())),
+                };
+
+                objects.insert(name.clone(), Object { geometry, materials: part_materials });
+            }
+        }
+
+        let mut world = World::new();
+
+        if let Some(value) = member(members, "background") {
+            world.set_background(self.load_background(value)?);
+        }
+
+        for entity in self.array(self.required(document, members, "entities")?, "entities")? {
+            for entity in self.load_entity(entity, &materials, &objects)? {
+                world.add(entity);
+            }
+        }
+
+        return Ok(Scene::new(world, camera, settings));
+    }
+
+    fn load_camera(&self, value: &JsonValue) -> Result<(Camera, f64), SceneError> {
+        let members = self.object(value, "the camera", &["look_from", "look_at", "v_up", "vfov", "aspect_ratio", "aperture", "focus_dist", "time0", "time1"])?;
+
+        let look_from = self.vec3(self.required(value, members, "look_from")?, "look_from")?;
+        let look_at = self.vec3(self.required(value, members, "look_at")?, "look_at")?;
+
+        let v_up = match member(members, "v_up") {
+            Some(v) => self.vec3(v, "v_up")?,
+            None => Vec3::new(0.0, 1.0, 0.0),
+        };
+
+        let vfov_value = self.required(value, members, "vfov")?;
+        let vfov = self.number(vfov_value, "vfov")?;
+        let aspect_ratio = self.optional_number(members, "aspect_ratio", 16.0 / 9.0)?;
+        let aperture = self.optional_number(members, "aperture", 0.0)?;
+        let focus_dist = self.optional_number(members, "focus_dist", (look_from - look_at).length())?;
+        let time0 = self.optional_number(members, "time0", 0.0)?;
+        let time1 = self.optional_number(members, "time1", time0)?;
+
+        if aspect_ratio <= 0.0 {
+            return Err(self.error(value, format!("aspect_ratio must be positive, found {}", aspect_ratio)));
+        }
+
+        if vfov <= 0.0 || vfov >= 180.0 {
+            return Err(self.error(vfov_value, format!("vfov must be between 0 and 180 degrees, found {}", vfov)));
+        }
+
+        if aperture < 0.0 {
+            return Err(self.error(value, format!("aperture must not be negative, found {}", aperture)));
+        }
+
+        return Ok((Camera::new(look_from, look_at, v_up, vfov, aspect_ratio, aperture, focus_dist, time0, time1), aspect_ratio));
+    }
+
+    fn load_settings(&self, value: Option<&JsonValue>, aspect_ratio: f64) -> Result<RenderSettings, SceneError> {
+        let members = match value {
+            Some(value) => self.object(value, "the render settings", &["image_width", "samples_per_pixel", "max_depth", "seed"])?,
+            None => &[],
+        };
+
+        let image_width = self.optional_positive_integer(members, "image_width", 2048)?;
+        let samples_per_pixel = self.optional_positive_integer(members, "samples_per_pixel", 100)?;
+        let max_depth = self.optional_positive_integer(members, "max_depth", 50)?;
+
+        let seed = match member(members, "seed") {
+            // Larger integers can't be represented exactly by a JSON number.
+            Some(value) => self.integer(value, "seed", 0.0, 9007199254740992.0)? as u64,
+            None => 0,
+        };
+
+        let image_height = (((image_width as f64) / aspect_ratio) as i32).max(1);
+
+        return Ok(RenderSettings::new(image_width, image_height, samples_per_pixel, max_depth, seed));
+    }
+
+    fn load_background(&self, value: &JsonValue) -> Result<Background, SceneError> {
+        let members = self.object(value, "the background", &["type", "color", "bottom", "top", "file", "rotation", "intensity", "sun_elevation", "sun_azimuth", "turbidity"])?;
+        let background_type = self.string(self.required(value, members, "type")?, "type")?;
+
+        return match background_type {
+            "solid" => {
+                let color = self.color(self.required(value, members, "color")?, "color")?;
+                Ok(Background::Solid(color))
+            },
+            "gradient" => {
+                let bottom = self.color(self.required(value, members, "bottom")?, "bottom")?;
+                let top = self.color(self.required(value, members, "top")?, "top")?;
+                Ok(Background::Gradient(bottom, top))
+            },
+            "environment" => {
+                let file_value = self.required(value, members, "file")?;
+                let file = self.string(file_value, "file")?;
+                let image = image::read_image(&self.file_path(file)).map_err(|e| self.error(file_value, e.to_string()))?;
+                let rotation = self.optional_number(members, "rotation", 0.0)?;
+                let intensity = self.optional_number(members, "intensity", 1.0)?;
+                Ok(Background::Environment(EnvironmentMap::new(image, rotation, intensity)))
+            },
+            "sky" => {
+                let elevation_value = self.required(value, members, "sun_elevation")?;
+                let sun_elevation = self.number(elevation_value, "sun_elevation")?;
+                let sun_azimuth = self.optional_number(members, "sun_azimuth", 0.0)?;
+                let turbidity = self.optional_number(members, "turbidity", 3.0)?;
+                let intensity = self.optional_number(members, "intensity", 1.0)?;
+
+                if !(0.0..=90.0).contains(&sun_elevation) {
+                    return Err(self.error(elevation_value, format!("sun_elevation must be between 0 and 90 degrees, found {}", sun_elevation)));
+                }
+
+                if !(1.7..=10.0).contains(&turbidity) {
+                    return Err(self.error(value, format!("turbidity must be between 1.7 and 10, found {}", turbidity)));
+                }
+
+                Ok(Background::Sky(Box::new(SkyBackground::new(Sky::new(sun_elevation, sun_azimuth, turbidity, intensity)))))
+            },
+            _ => Err(self.error(value, format!("unknown background type '{}', expected solid, gradient, environment or sky", background_type))),
+        };
+    }
+
+    fn load_texture(&self, value: &JsonValue, textures: &Textures) -> Result<Arc<dyn Texture>, SceneError> {
+        let members = self.object(value, "a texture", &["type", "color", "size", "even", "odd", "file", "wrap", "pattern", "seed", "scale", "octaves", "low", "high"])?;
+        let texture_type = self.string(self.required(value, members, "type")?, "type")?;
+
+        return match texture_type {
+            "solid" => {
+                let color = self.color(self.required(value, members, "color")?, "color")?;
+                Ok(Arc::new(SolidColor::new(color)))
+            },
+            "checker" => {
+                let size = self.optional_number(members, "size", 1.0)?;
+                let even = self.texture(self.required(value, members, "even")?, "even", textures)?;
+                let odd = self.texture(self.required(value, members, "odd")?, "odd", textures)?;
+
+                if size <= 0.0 {
+                    return Err(self.error(value, format!("size must be positive, found {}", size)));
+                }
+
+                Ok(Arc::new(CheckerTexture::new(size, even, odd)))
+            },
+            "image" => {
+                let file_value = self.required(value, members, "file")?;
+                let file = self.string(file_value, "file")?;
+                let image = image::read_image(&self.file_path(file)).map_err(|e| self.error(file_value, e.to_string()))?;
+
+                let wrap_mode = match member(members, "wrap") {
+                    Some(wrap_value) => {
+                        let name = self.string(wrap_value, "wrap")?;
+
+                        match WrapMode::from_name(name) {
+                            Some(wrap_mode) => wrap_mode,
+                            None => return Err(self.error(wrap_value, format!("unknown wrap mode '{}', expected repeat or clamp", name))),
+                        }
+                    },
+                    None => WrapMode::Repeat,
+                };
+
+                Ok(Arc::new(ImageTexture::new(image, wrap_mode)))
+            },
+            "noise" => {
+                let pattern = match member(members, "pattern") {
+                    Some(pattern_value) => {
+                        let name = self.string(pattern_value, "pattern")?;
+
+                        match NoisePattern::from_name(name) {
+                            Some(pattern) => pattern,
+                            None => return Err(self.error(pattern_value, format!("unknown noise pattern '{}', expected noise, turbulence, marble or wood", name))),
+                        }
+                    },
+                    None => NoisePattern::Noise,
+                };
+
+                let seed = match member(members, "seed") {
+                    Some(seed_value) => self.integer(seed_value, "seed", 0.0, 9007199254740992.0)? as u64,
+                    None => 0,
+                };
+
+                let scale = self.optional_number(members, "scale", 1.0)?;
+                let octaves = self.optional_positive_integer(members, "octaves", 7)?;
+
+                let low = match member(members, "low") {
+                    Some(low_value) => self.color(low_value, "low")?,
+                    None => Color::new(0.0, 0.0, 0.0),
+                };
+
+                let high = match member(members, "high") {
+                    Some(high_value) => self.color(high_value, "high")?,
+                    None => Color::new(1.0, 1.0, 1.0),
+                };
+
+                Ok(Arc::new(NoiseTexture::new(pattern, seed, scale, octaves as u32, low, high)))
+            },
+            _ => Err(self.error(value, format!("unknown texture type '{}', expected solid, checker, image or noise", texture_type))),
+        };
+    }
+
+    // A texture given as a color, the name of a texture or a texture
+    // definition.
+    fn texture(&self, value: &JsonValue, what: &str, textures: &Textures) -> Result<Arc<dyn Texture>, SceneError> {
+        return match value.kind() {
+            JsonKind::Array(_) => Ok(Arc::new(SolidColor::new(self.color(value, what)?))),
+            JsonKind::String(name) => match textures.get(name) {
+                Some(texture) => Ok(texture.clone()),
+                None => Err(self.error(value, format!("unknown texture '{}'", name))),
+            },
+            JsonKind::Object(_) => self.load_texture(value, textures),
+            _ => Err(self.error(value, format!("{} must be a color, a texture name or a texture, found {}", what, value.type_name()))),
+        };
+    }
+
+    fn load_material(&self, value: &JsonValue, textures: &Textures) -> Result<Arc<dyn Material>, SceneError> {
+        let members = self.object(value, "a material", &["type", "albedo", "fuzz", "refraction_index", "emit"])?;
+        let material_type = self.string(self.required(value, members, "type")?, "type")?;
+
+        return match material_type {
+            "lambertian" => {
+                let albedo = self.texture(self.required(value, members, "albedo")?, "albedo", textures)?;
+                Ok(Arc::new(Lambertian::new_textured(albedo)))
+            },
+            "metal" => {
+                let albedo = self.texture(self.required(value, members, "albedo")?, "albedo", textures)?;
+                let fuzz = self.optional_number(members, "fuzz", 0.0)?;
+                Ok(Arc::new(Metal::new_textured(albedo, fuzz)))
+            },
+            "dielectric" => {
+                let refraction_index = self.number(self.required(value, members, "refraction_index")?, "refraction_index")?;
+                Ok(Arc::new(Dielectric::new(refraction_index)))
+            },
+            "diffuse_light" => {
+                let emit = self.color(self.required(value, members, "emit")?, "emit")?;
+                Ok(Arc::new(DiffuseLight::new(emit)))
+            },
+            _ => Err(self.error(value, format!("unknown material type '{}', expected lambertian, metal, dielectric or diffuse_light", material_type))),
+        };
+    }
+
+    fn material(&self, value: &JsonValue, members: &Members, materials: &HashMap<String, Arc<dyn Material>>) -> Result<Arc<dyn Material>, SceneError> {
+        let name_value = self.required(value, members, "material")?;
+        let name = self.string(name_value, "material")?;
+
+        return match materials.get(name) {
+            Some(material) => Ok(material.clone()),
+            None => Err(self.error(name_value, format!("unknown material '{}'", name))),
+        };
+    }
+
+    fn load_entity(&self, value: &JsonValue, materials: &HashMap<String, Arc<dyn Material>>, objects: &Objects) -> Result<Vec<Entity>, SceneError> {
+        let type_value = match value.kind() {
+            JsonKind::Object(members) => self.required(value, members, "type")?,
+            _ => return Err(self.error(value, format!("an entity must be an object, found {}", value.type_name()))),
+        };
+
+        let entity_type = self.string(type_value, "type")?;
+        let mut entities = Vec::new();
+
+        match entity_type {
+            "sphere" => {
+                let members = self.object(value, "a sphere", &["type", "center", "radius", "material", "transform", "keyframes"])?;
+                let center = self.vec3(self.required(value, members, "center")?, "center")?;
+                let radius = self.number(self.required(value, members, "radius")?, "radius")?;
+                let material = self.material(value, members, materials)?;
+
+                entities.push(Entity::new(Box::new(Sphere::new_stationary(center, radius)), material));
+            },
+            "moving_sphere" => {
+                let members = self.object(value, "a moving sphere", &["type", "center0", "center1", "radius", "time0", "time1", "material", "transform", "keyframes"])?;
+                let center0 = self.vec3(self.required(value, members, "center0")?, "center0")?;
+                let center1 = self.vec3(self.required(value, members, "center1")?, "center1")?;
+                let radius = self.number(self.required(value, members, "radius")?, "radius")?;
+                let time0 = self.optional_number(members, "time0", 0.0)?;
+                let time1 = self.optional_number(members, "time1", 1.0)?;
+                let material = self.material(value, members, materials)?;
+
+                if time1 <= time0 {
+                    return Err(self.error(value, "time1 must be greater than time0".to_string()));
+                }
+
+                entities.push(Entity::new(Box::new(Sphere::new(center0, center1, radius, time0, time1)), material));
+            },
+            "triangle" => {
+                let members = self.object(value, "a triangle", &["type", "vertices", "normals", "material", "transform", "keyframes"])?;
+                let vertices = self.vec3_list(self.required(value, members, "vertices")?, "vertices", Some(3))?;
+                let material = self.material(value, members, materials)?;
+
+                let triangle = match member(members, "normals") {
+                    Some(normals_value) => {
+                        let normals = self.vec3_list(normals_value, "normals", Some(3))?;
+                        Triangle::new_with_normals(vertices[0], vertices[1], vertices[2], normals[0], normals[1], normals[2])
+                    },
+                    None => Triangle::new(vertices[0], vertices[1], vertices[2]),
+                };
+
+                entities.push(Entity::new(Box::new(triangle), material));
+            },
+            "mesh" => {
+                let members = self.object(value, "a mesh", &["type", "positions", "normals", "faces", "material", "transform", "keyframes"])?;
+                let positions = self.vec3_list(self.required(value, members, "positions")?, "positions", None)?;
+                let material = self.material(value, members, materials)?;
+
+                let normals = match member(members, "normals") {
+                    Some(normals_value) => self.vec3_list(normals_value, "normals", Some(positions.len()))?,
+                    None => Vec::new(),
+                };
+
+                let mut faces = Vec::new();
+
+                for face_value in self.array(self.required(value, members, "faces")?, "faces")? {
+                    let (a, b, c) = self.triple(face_value, "a face")?;
+                    let mut indices = [0u32; 3];
+
+                    for (index, &n) in indices.iter_mut().zip(&[a, b, c]) {
+                        if n < 0.0 || n.fract() != 0.0 || n as usize >= positions.len() {
+                            return Err(self.error(face_value, format!("vertex index {} out of range, the mesh has {} positions", n, positions.len())));
+                        }
+
+                        *index = n as u32;
+                    }
+
+                    faces.push(MeshFace::new(indices, if normals.is_empty() { None } else { Some(indices) }, None, 0));
+                }
+
+                let mesh = TriangleMesh::new(positions, normals, Vec::new(), faces).map_err(|e| self.error(value, e))?;
+                entities.push(Entity::new(Box::new(mesh), material));
+            },
+            "obj" => {
+                let members = self.object(value, "an OBJ model", &["type", "file", "transform", "keyframes"])?;
+                let file_value = self.required(value, members, "file")?;
+                let file = self.string(file_value, "file")?;
+                let obj_path = self.file_path(file);
+
+                entities = obj::load_obj(&obj_path).map_err(|e| self.error(file_value, e.to_string()))?;
+            },
+            "instance" => {
+                let members = self.object(value, "an instance", &["type", "object", "material", "materials", "transform", "keyframes"])?;
+                let name_value = self.required(value, members, "object")?;
+                let name = self.string(name_value, "object")?;
+
+                let object = match objects.get(name) {
+                    Some(object) => object,
+                    None => return Err(self.error(name_value, format!("unknown object '{}'", name))),
+                };
+
+                let object_materials = self.instance_materials(value, members, name, object, materials)?;
+                let keyframes = self.load_keyframes(value, members)?.unwrap_or_else(|| vec![(0.0, Mat4::identity())]);
+
+                // The instance's transform is applied to the shared geometry
+                // directly, instead of to a copy below.
+                return Ok(vec![Entity::new_with_materials(self.transformed(object.geometry.clone(), &keyframes, value)?, object_materials)]);
+            },
+            _ => return Err(self.error(type_value, format!("unknown entity type '{}', expected sphere, moving_sphere, triangle, mesh, obj or instance", entity_type))),
+        }
+
+        let keyframes = match self.load_keyframes(value, self.members(value, "an entity")?)? {
+            Some(keyframes) => keyframes,
+            None => return Ok(entities),
+        };
+
+        return entities.into_iter().map(|entity| {
+            return Ok(Entity::new_with_materials(self.transformed(Arc::from(entity.geometry), &keyframes, value)?, entity.materials));
+        }).collect();
+    }
+
+    // The materials of an instance of an object. A "material" replaces all
+    // of the object's materials, and "materials" maps the names of some of
+    // them to their replacements, for example to change only the leaves of
+    // a tree.
+    fn instance_materials(&self, value: &JsonValue, members: &Members, name: &str, object: &Object, materials: &HashMap<String, Arc<dyn Material>>) -> Result<Vec<Arc<dyn Material>>, SceneError> {
+        let mut object_materials = object.materials.clone();
+
+        if member(members, "material").is_some() {
+            if member(members, "materials").is_some() {
+                return Err(self.error(value, "an instance can't have both a material and materials".to_string()));
+            }
+
+            let material = self.material(value, members, materials)?;
+            return Ok(vec![material; object_materials.len()]);
+        }
+
+        if let Some(replacements_value) = member(members, "materials") {
+            for (original_name, replacement_value) in self.members(replacements_value, "materials")? {
+                let original = match materials.get(original_name) {
+                    Some(original) => original,
+                    None => return Err(self.error(replacement_value, format!("unknown material '{}'", original_name))),
+                };
+
+                let replacement_name = self.string(replacement_value, "a material")?;
+
+                let replacement = match materials.get(replacement_name) {
+                    Some(replacement) => replacement,
+                    None => return Err(self.error(replacement_value, format!("unknown material '{}'", replacement_name))),
+                };
+
+                if !object.materials.iter().any(|material| Arc::ptr_eq(material, original)) {
+                    return Err(self.error(replacement_value, format!("object '{}' doesn't use material '{}'", name, original_name)));
+                }
+
+                for (material, object_material) in object_materials.iter_mut().zip(&object.materials) {
+                    if Arc::ptr_eq(object_material, original) {
+                        *material = replacement.clone();
+                    }
+                }
+            }
+        }
+
+        return Ok(object_materials);
+    }
+
+    // A fixed transform when there is one keyframe, and an animated one
+    // otherwise.
+    fn transformed(&self, geometry: Arc<dyn Geometry>, keyframes: &[(f64, Mat4)], value: &JsonValue) -> Result<Box<dyn Geometry>, SceneError> {
+        let transformed: Option<Box<dyn Geometry>> = if keyframes.len() == 1 {
+            Transform::new(geometry, keyframes[0].1).map(|transform| Box::new(transform) as Box<dyn Geometry>)
+        } else {
+            AnimatedTransform::new(geometry, keyframes.to_vec()).map(|transform| Box::new(transform) as Box<dyn Geometry>)
+        };
+
+        return match transformed {
+            Some(transformed) => Ok(transformed),
+            None => Err(self.error(value, "the transform can't be inverted".to_string())),
+        };
+    }
+
+    // An entity's "transform", as a single keyframe, or its "keyframes", a
+    // list of objects with a "time" and a "transform" in increasing order of
+    // time. Rotations between keyframes take the shorter way around, so
+    // turns of half a revolution or more need keyframes in between. None if
+    // the entity has neither.
+    fn load_keyframes(&self, value: &JsonValue, members: &Members) -> Result<Option<Vec<(f64, Mat4)>>, SceneError> {
+        let keyframes_value = match (member(members, "transform"), member(members, "keyframes")) {
+            (Some(_), Some(_)) => return Err(self.error(value, "an entity can't have both a transform and keyframes".to_string())),
+            (Some(transform_value), None) => return Ok(Some(vec![(0.0, self.load_transform(transform_value)?)])),
+            (None, Some(keyframes_value)) => keyframes_value,
+            (None, None) => return Ok(None),
+        };
+
+        let mut keyframes: Vec<(f64, Mat4)> = Vec::new();
+
+        for keyframe_value in self.array(keyframes_value, "keyframes")? {
+            let members = self.object(keyframe_value, "a keyframe", &["time", "transform"])?;
+            let time = self.number(self.required(keyframe_value, members, "time")?, "time")?;
+            let object_to_world = self.load_transform(self.required(keyframe_value, members, "transform")?)?;
+
+            if let Some(&(previous_time, _)) = keyframes.last() {
+                if time <= previous_time {
+                    return Err(self.error(keyframe_value, "keyframe times must be increasing".to_string()));
+                }
+            }
+
+            keyframes.push((time, object_to_world));
+        }
+
+        if keyframes.is_empty() {
+            return Err(self.error(keyframes_value, "keyframes must not be empty".to_string()));
+        }
+
+        return Ok(Some(keyframes));
+    }
+
+    // A list of steps, each of which translates, rotates about an axis by an
+    // angle in degrees, or scales by a number or by a factor per axis. The
+    // steps are applied in order.
+    fn load_transform(&self, value: &JsonValue) -> Result<Mat4, SceneError> {
+        let mut matrix = Mat4::identity();
+
+        for step in self.array(value, "transform")? {
+            let members = self.object(step, "a transform step", &["translate", "rotate", "angle", "scale"])?;
+
+            let step_matrix = if let Some(offset) = member(members, "translate") {
+                Mat4::translation(self.vec3(offset, "translate")?)
+            } else if let Some(axis_value) = member(members, "rotate") {
+                let axis = self.vec3(axis_value, "rotate")?;
+                let angle = self.number(self.required(step, members, "angle")?, "angle")?;
+
+                if axis.length_squared() == 0.0 {
+                    return Err(self.error(axis_value, "the rotation axis must not be zero".to_string()));
+                }
+
+                Mat4::rotation(axis, angle)
+            } else if let Some(factors) = member(members, "scale") {
+                match factors.kind() {
+                    JsonKind::Number(factor) => Mat4::scaling(Vec3::new(*factor, *factor, *factor)),
+                    _ => Mat4::scaling(self.vec3(factors, "scale")?),
+                }
+            } else {
+                return Err(self.error(step, "a transform step must have one of translate, rotate or scale".to_string()));
+            };
+
+            if members.len() != if member(members, "rotate").is_some() { 2 } else { 1 } {
+                return Err(self.error(step, "a transform step must have exactly one of translate, rotate or scale".to_string()));
+            }
+
+            matrix = step_matrix * matrix;
+        }
+
+        return Ok(matrix);
+    }
+
+    fn vec3_list(&self, value: &JsonValue, what: &str, expected_len: Option<usize>) -> Result<Vec<Vec3>, SceneError> {
+        let elements = self.array(value, what)?;
+
+        if let Some(expected_len) = expected_len {
+            if elements.len() != expected_len {
+                return Err(self.error(value, format!("{} must have {} elements, found {}", what, expected_len, elements.len())));
+            }
+        }
+
+        return elements.iter().map(|element| self.vec3(element, what)).collect();
+    }
+}
+
+fn member<'v>(members: &'v Members, key: &str) -> Option<&'v JsonValue> {
+    return members.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = r#""camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40 }"#;
+    const MATERIALS: &str = r#""materials": { "red": { "type": "lambertian", "albedo": [0.8, 0.1, 0.1] } }"#;
+
+    // The error a scene with the given camera, materials and entities gives,
+    // as it would be reported.
+    fn error(camera: &str, members: &str) -> String {
+        let text = format!("{{\n{},\n{},\n{}\n}}", camera, MATERIALS, members);
+
+        return match parse_scene(&text, Path::new("test.json")) {
+            Ok(_) => panic!("the scene loaded"),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    #[test]
+    fn loads_a_scene() {
+        let text = format!("{{ {}, {}, \"entities\": [{{ \"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"red\" }}] }}", CAMERA, MATERIALS);
+        assert!(parse_scene(&text, Path::new("test.json")).is_ok());
+    }
+
+    #[test]
+    fn unknown_member_line() {
+        let entities = "\"entities\": [\n{ \"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1,\n\"material\": \"red\", \"colour\": 1 }\n]";
+        let message = error(CAMERA, entities);
+
+        assert!(message.starts_with("test.json:6: "), "{}", message);
+        assert!(message.contains("colour"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_material() {
+        let entities = "\"entities\": [\n{ \"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"blue\" }\n]";
+        let message = error(CAMERA, entities);
+
+        assert!(message.starts_with("test.json:5: "), "{}", message);
+        assert!(message.contains("blue"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_object() {
+        let entities = "\"entities\": [\n{ \"type\": \"instance\", \"object\": \"tree\" }\n]";
+        let message = error(CAMERA, entities);
+
+        assert!(message.starts_with("test.json:5: "), "{}", message);
+        assert!(message.contains("unknown object 'tree'"), "{}", message);
+    }
+
+    #[test]
+    fn singular_transform() {
+        let entities = "\"entities\": [\n{ \"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"red\",\n\"transform\": [{ \"scale\": [1, 0, 1] }] }\n]";
+        let message = error(CAMERA, entities);
+
+        assert!(message.contains("can't be inverted"), "{}", message);
+    }
+
+    #[test]
+    fn camera_ranges() {
+        let entities = "\"entities\": []";
+
+        assert!(error(r#""camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 0 }"#, entities).contains("vfov"));
+        assert!(error(r#""camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 180 }"#, entities).contains("vfov"));
+        assert!(error(r#""camera": { "look_from": [0, 0, 5], "look_at": [0, 0, 0], "vfov": 40, "aperture": -0.1 }"#, entities).contains("aperture"));
+    }
+}
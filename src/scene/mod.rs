@@ -0,0 +1,10 @@
+mod error;
+mod json;
+mod loader;
+#[allow(clippy::module_inception)]
+mod scene;
+
+pub use error::*;
+pub use json::*;
+pub use loader::*;
+pub use scene::*;
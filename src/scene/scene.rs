@@ -0,0 +1,47 @@
+use crate::bvh::SplitMethod;
+use crate::camera::Camera;
+use crate::render::RenderSettings;
+
+use crate::World;
+
+// Everything needed to render an image: what to render, from where, and how.
+pub struct Scene {
+    world: World,
+    camera: Camera,
+    settings: RenderSettings,
+}
+
+impl Scene {
+    // Builds the world's bounding volume hierarchy for the camera's shutter
+    // interval and finds its lights, so no objects can be added afterwards.
+    pub fn new(mut world: World, camera: Camera, settings: RenderSettings) -> Scene {
+        world.build_bvh(camera.time0(), camera.time1(), SplitMethod::Sah);
+        world.find_lights();
+
+        return Scene {
+            world,
+            camera,
+            settings,
+        };
+    }
+
+    pub fn world(&self) -> &World {
+        return &self.world;
+    }
+
+    pub fn camera(&self) -> &Camera {
+        return &self.camera;
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        return &self.settings;
+    }
+
+    pub fn set_settings(&mut self, settings: RenderSettings) {
+        self.settings = settings;
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.camera.set_aspect_ratio(aspect_ratio);
+    }
+}
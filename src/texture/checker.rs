@@ -0,0 +1,33 @@
+use super::*;
+
+use crate::math::*;
+
+use std::sync::Arc;
+
+// A checkerboard of cubes with sides of the given size in space, so that it
+// doesn't depend on the surface coordinates and never stretches.
+pub struct CheckerTexture {
+    size: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(size: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> CheckerTexture {
+        return CheckerTexture { size, even, odd };
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, position: Vec3) -> Color {
+        let x = (position.x() / self.size).floor() as i64;
+        let y = (position.y() / self.size).floor() as i64;
+        let z = (position.z() / self.size).floor() as i64;
+
+        if (x + y + z).rem_euclid(2) == 0 {
+            return self.even.value(u, v, position);
+        }
+
+        return self.odd.value(u, v, position);
+    }
+}
@@ -0,0 +1,125 @@
+use super::*;
+
+use crate::image::Image;
+use crate::math::*;
+
+// What an image texture shows outside of the unit square of surface
+// coordinates.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<WrapMode> {
+        return match name {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            _ => None,
+        };
+    }
+}
+
+// An image mapped onto the unit square of surface coordinates, with v
+// pointing up, so that (0, 0) is the bottom left corner of the image. Colors
+// are filtered bilinearly between the pixel centers.
+pub struct ImageTexture {
+    image: Image,
+    wrap_mode: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image, wrap_mode: WrapMode) -> ImageTexture {
+        return ImageTexture { image, wrap_mode };
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Color {
+        let width = self.image.width() as i64;
+        let height = self.image.height() as i64;
+
+        let (x, y) = match self.wrap_mode {
+            WrapMode::Repeat => (x.rem_euclid(width), y.rem_euclid(height)),
+            WrapMode::Clamp => (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+        };
+
+        return self.image.row(y as usize)[x as usize];
+    }
+
+    // Brings a surface coordinate into the unit interval, so that the pixel
+    // coordinates stay small for huge values. Infinities and NaNs, which
+    // have no place on the image, become zero.
+    fn wrap(&self, t: f64) -> f64 {
+        if !t.is_finite() {
+            return 0.0;
+        }
+
+        return match self.wrap_mode {
+            WrapMode::Repeat => t - t.floor(),
+            WrapMode::Clamp => t.clamp(0.0, 1.0),
+        };
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _position: Vec3) -> Color {
+        let x = self.wrap(u) * self.image.width() as f64 - 0.5;
+        let y = (1.0 - self.wrap(v)) * self.image.height() as f64 - 0.5;
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let x0 = x0 as i64;
+        let y0 = y0 as i64;
+
+        let top = (1.0 - fx) * self.pixel(x0, y0) + fx * self.pixel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.pixel(x0, y0 + 1) + fx * self.pixel(x0 + 1, y0 + 1);
+
+        return (1.0 - fy) * top + fy * bottom;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_texture(wrap_mode: WrapMode) -> ImageTexture {
+        let mut image = Image::new(4, 2);
+
+        for y in 0..2 {
+            for (x, pixel) in image.row_mut(y).iter_mut().enumerate() {
+                *pixel = Color::new(x as f64, y as f64, 1.0);
+            }
+        }
+
+        return ImageTexture::new(image, wrap_mode);
+    }
+
+    #[test]
+    fn repeat_wraps() {
+        let texture = test_texture(WrapMode::Repeat);
+        let position = Vec3::new_default();
+
+        for &(u, v) in &[(0.3, 0.6), (0.9, 0.1)] {
+            let a = texture.value(u, v, position);
+            let b = texture.value(u + 3.0, v - 2.0, position);
+
+            assert!((a.r() - b.r()).abs() < 1e-9 && (a.g() - b.g()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn extreme_coordinates() {
+        for &wrap_mode in &[WrapMode::Repeat, WrapMode::Clamp] {
+            let texture = test_texture(wrap_mode);
+
+            for &u in &[1e300, -1e300, f64::INFINITY, f64::NEG_INFINITY, f64::NAN, 9.3e18] {
+                let color = texture.value(u, u, Vec3::new_default());
+
+                assert!(color.r().is_finite() && color.g().is_finite() && color.b().is_finite());
+            }
+        }
+    }
+}
@@ -0,0 +1,14 @@
+mod checker;
+mod imagetexture;
+mod noise;
+mod perlin;
+mod solidcolor;
+#[allow(clippy::module_inception)]
+mod texture;
+
+pub use checker::*;
+pub use imagetexture::*;
+pub use noise::*;
+pub use perlin::*;
+pub use solidcolor::*;
+pub use texture::*;
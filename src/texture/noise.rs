@@ -0,0 +1,101 @@
+use super::*;
+
+use crate::math::*;
+
+// How a noise texture turns noise into a value between 0 and 1, which
+// selects a color between the low and high colors.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoisePattern {
+    // Plain smooth noise.
+    Noise,
+    // Fractal noise, with detail at every scale.
+    Turbulence,
+    // Bands along z, distorted by turbulence into veins.
+    Marble,
+    // Rings around the y axis, distorted by turbulence.
+    Wood,
+}
+
+impl NoisePattern {
+    pub fn from_name(name: &str) -> Option<NoisePattern> {
+        return match name {
+            "noise" => Some(NoisePattern::Noise),
+            "turbulence" => Some(NoisePattern::Turbulence),
+            "marble" => Some(NoisePattern::Marble),
+            "wood" => Some(NoisePattern::Wood),
+            _ => None,
+        };
+    }
+}
+
+// A procedural texture made from Perlin noise in space. The scale is the
+// frequency of the noise, or of the bands and rings of marble and wood,
+// whose distortion keeps the frequency of unscaled noise.
+pub struct NoiseTexture {
+    perlin: Perlin,
+    pattern: NoisePattern,
+    scale: f64,
+    octaves: u32,
+    low: Color,
+    high: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(pattern: NoisePattern, seed: u64, scale: f64, octaves: u32, low: Color, high: Color) -> NoiseTexture {
+        return NoiseTexture {
+            perlin: Perlin::new(seed),
+            pattern,
+            scale,
+            octaves,
+            low,
+            high,
+        };
+    }
+
+    fn pattern_value(&self, position: Vec3) -> f64 {
+        let p = self.scale * position;
+
+        return match self.pattern {
+            NoisePattern::Noise => 0.5 * (1.0 + self.perlin.noise(p)),
+            NoisePattern::Turbulence => self.perlin.turbulence(p, self.octaves),
+            NoisePattern::Marble => 0.5 * (1.0 + (p.z() + 10.0 * self.perlin.turbulence(position, self.octaves)).sin()),
+            NoisePattern::Wood => {
+                let rings = (p.x() * p.x() + p.z() * p.z()).sqrt() + 2.0 * self.perlin.turbulence(position, self.octaves);
+                rings - rings.floor()
+            },
+        };
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, position: Vec3) -> Color {
+        let t = clamp(self.pattern_value(position), 0.0, 1.0);
+
+        return (1.0 - t) * self.low + t * self.high;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::random::Sampler;
+
+    #[test]
+    fn values_between_low_and_high() {
+        let low = Color::new(0.1, 0.2, 0.3);
+        let high = Color::new(0.9, 0.6, 0.3);
+        let mut sampler = Sampler::new(2);
+
+        for &pattern in &[NoisePattern::Noise, NoisePattern::Turbulence, NoisePattern::Marble, NoisePattern::Wood] {
+            let texture = NoiseTexture::new(pattern, 11, 4.0, 7, low, high);
+
+            for _ in 0..1000 {
+                let position = Vec3::new(sampler.double_range(-10.0, 10.0), sampler.double_range(-10.0, 10.0), sampler.double_range(-10.0, 10.0));
+                let color = texture.value(0.0, 0.0, position);
+
+                assert!((0.1..=0.9).contains(&color.r()) && (0.2..=0.6).contains(&color.g()) && (color.b() - 0.3).abs() < 1e-12);
+            }
+        }
+    }
+}
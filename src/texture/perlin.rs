@@ -0,0 +1,155 @@
+use crate::math::*;
+use crate::random::Sampler;
+use crate::random_unit_vector;
+
+const POINT_COUNT: usize = 256;
+
+// Ken Perlin's gradient noise: random unit gradients at the points of the
+// integer lattice, interpolated smoothly in between. The same seed always
+// gives the same noise.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutation_x: Vec<usize>,
+    permutation_y: Vec<usize>,
+    permutation_z: Vec<usize>,
+}
+
+fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+    let mut values: Vec<usize> = (0..POINT_COUNT).collect();
+
+    for i in (1..POINT_COUNT).rev() {
+        let j = (sampler.next_u64() % (i as u64 + 1)) as usize;
+        values.swap(i, j);
+    }
+
+    return values;
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut sampler = Sampler::new(seed);
+        let gradients = (0..POINT_COUNT).map(|_| random_unit_vector(&mut sampler)).collect();
+
+        return Perlin {
+            gradients,
+            permutation_x: permutation(&mut sampler),
+            permutation_y: permutation(&mut sampler),
+            permutation_z: permutation(&mut sampler),
+        };
+    }
+
+    // Noise at a point, roughly between -1 and 1, and zero at the lattice
+    // points.
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let fx = p.x().floor();
+        let fy = p.y().floor();
+        let fz = p.z().floor();
+
+        let u = p.x() - fx;
+        let v = p.y() - fy;
+        let w = p.z() - fz;
+
+        // Hermite smoothing hides the lattice.
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let i = fx as i64;
+        let j = fy as i64;
+        let k = fz as i64;
+        let mask = POINT_COUNT as i64 - 1;
+
+        let mut sum = 0.0;
+
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.permutation_x[((i + di) & mask) as usize]
+                        ^ self.permutation_y[((j + dj) & mask) as usize]
+                        ^ self.permutation_z[((k + dk) & mask) as usize];
+
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - di, v - dj, w - dk);
+
+                    sum += (di * uu + (1.0 - di) * (1.0 - uu))
+                        * (dj * vv + (1.0 - dj) * (1.0 - vv))
+                        * (dk * ww + (1.0 - dk) * (1.0 - ww))
+                        * dot(self.gradients[index], weight);
+                }
+            }
+        }
+
+        return sum;
+    }
+
+    // Fractal sum of the absolute noise over octaves of doubling frequency
+    // and halving amplitude, between 0 and about 1.
+    pub fn turbulence(&self, p: Vec3, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut point = p;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            sum += weight * self.noise(point).abs();
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+
+        return sum;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_points() -> Vec<Vec3> {
+        let mut sampler = Sampler::new(1);
+
+        return (0..10000).map(|_| Vec3::new(sampler.double_range(-50.0, 50.0), sampler.double_range(-50.0, 50.0), sampler.double_range(-50.0, 50.0))).collect();
+    }
+
+    #[test]
+    fn seed_determines_noise() {
+        let a = Perlin::new(3);
+        let b = Perlin::new(3);
+        let c = Perlin::new(4);
+        let points = test_points();
+
+        assert!(points.iter().all(|&p| a.noise(p) == b.noise(p)));
+        assert!(points.iter().any(|&p| a.noise(p) != c.noise(p)));
+    }
+
+    #[test]
+    fn zero_at_lattice_points() {
+        let perlin = Perlin::new(5);
+
+        for x in -3..4 {
+            for y in -3..4 {
+                for z in [-300, 0, 255, 256, 1000] {
+                    assert_eq!(perlin.noise(Vec3::new(x as f64, y as f64, z as f64)), 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_range() {
+        let perlin = Perlin::new(6);
+
+        assert!(test_points().iter().all(|&p| perlin.noise(p).abs() <= 1.0));
+    }
+
+    // Every octave adds at most half as much as the one before, so the sum
+    // stays below twice the largest noise.
+    #[test]
+    fn turbulence_range() {
+        let perlin = Perlin::new(7);
+        let points = test_points();
+        let values: Vec<f64> = points.iter().map(|&p| perlin.turbulence(p, 7)).collect();
+
+        assert!(values.iter().all(|&value| (0.0..2.0).contains(&value)));
+        assert!(values.iter().sum::<f64>() / values.len() as f64 <= 1.0);
+        assert_eq!(perlin.turbulence(points[0], 0), 0.0);
+    }
+}
@@ -0,0 +1,19 @@
+use super::*;
+
+use crate::math::*;
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        return SolidColor { color };
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _position: Vec3) -> Color {
+        return self.color;
+    }
+}
@@ -0,0 +1,8 @@
+use crate::math::*;
+
+// A color that varies over a surface, looked up by the surface coordinates
+// (u, v) of a hit point or by its position in the space of the object, so
+// that the texture moves along with any transform.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, position: Vec3) -> Color;
+}